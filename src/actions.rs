@@ -0,0 +1,519 @@
+//! The things the player can do with the keyboard or a gamepad, and which
+//! keys and buttons do them. Systems ask [Actions] whether an action is
+//! happening rather than looking at keys directly, so everything can be
+//! changed.
+//!
+//! The bindings are kept in the config file as lines like `key.left = Left O`
+//! or `pad.jump = South`, using bevy's names for the keys and buttons. Actions
+//! that aren't in the file use their defaults.
+
+use std::hash::Hash;
+
+use bevy::{
+  ecs::system::SystemParam,
+  input::{gamepad::{GamepadConnection, GamepadConnectionEvent}, InputSystem},
+  prelude::*,
+  reflect::{DynamicEnum, DynamicVariant, TypeInfo, Typed},
+};
+
+use crate::{
+  config::Config,
+  keyboard_matrix::{InputMode, Matrix},
+};
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+  fn build(&self, app: &mut App) {
+    app.init_resource::<Input<PadInput>>();
+    app.add_systems(PreStartup, load_bindings);
+    app.configure_set(PreUpdate, ReadInput.after(InputSystem));
+    app.add_systems(PreUpdate, (report_gamepads, update_pad_input).in_set(ReadInput));
+  }
+}
+
+/// The systems that read gamepads. Anything that checks actions before
+/// `Update` should run after these.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReadInput;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+  Left,
+  Right,
+  Jump,
+  Pause,
+  Mute,
+  /// Goes back to the title screen while paused.
+  Quit,
+  /// Starts the game from the title screen.
+  Start,
+  /// Shows the define keys screen from the title screen.
+  DefineKeys,
+  /// Switches between standard and authentic keys on the title screen.
+  ToggleInputMode,
+  QuickSave,
+  QuickLoad,
+  /// Saves what's on screen as a Spectrum `.scr` file.
+  ExportScreen,
+  ToggleClash,
+  CyclePalette,
+  ToggleDebug,
+  DebugUnlockPortal,
+  DebugNextCavern,
+  DebugPreviousCavern,
+  DebugAddScore,
+  DebugSlower,
+  DebugFaster,
+  /// Freezes the game, or starts it running again.
+  DebugFreeze,
+  /// Moves the game on by one tick while frozen.
+  DebugStep,
+  /// Runs the game backwards while held.
+  DebugRewind,
+}
+
+impl Action {
+  pub const ALL: [Action; 24] = [
+    Action::Left,
+    Action::Right,
+    Action::Jump,
+    Action::Pause,
+    Action::Mute,
+    Action::Quit,
+    Action::Start,
+    Action::DefineKeys,
+    Action::ToggleInputMode,
+    Action::QuickSave,
+    Action::QuickLoad,
+    Action::ExportScreen,
+    Action::ToggleClash,
+    Action::CyclePalette,
+    Action::ToggleDebug,
+    Action::DebugUnlockPortal,
+    Action::DebugNextCavern,
+    Action::DebugPreviousCavern,
+    Action::DebugAddScore,
+    Action::DebugSlower,
+    Action::DebugFaster,
+    Action::DebugFreeze,
+    Action::DebugStep,
+    Action::DebugRewind,
+  ];
+
+  /// The actions used while playing, which can be changed on the define keys
+  /// screen.
+  pub const PLAYING: [Action; 5] = [Action::Left, Action::Right, Action::Jump, Action::Pause, Action::Mute];
+
+  /// The actions only used on the title screen, whose keys can be used for
+  /// something else while playing.
+  pub const TITLE: [Action; 3] = [Action::Start, Action::DefineKeys, Action::ToggleInputMode];
+
+  /// The name of the action, as shown to the player.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Action::Left => "Left",
+      Action::Right => "Right",
+      Action::Jump => "Jump",
+      Action::Pause => "Pause",
+      Action::Mute => "Mute",
+      Action::Quit => "Quit to title",
+      Action::Start => "Start",
+      Action::DefineKeys => "Define keys",
+      Action::ToggleInputMode => "Keys",
+      Action::QuickSave => "Quick save",
+      Action::QuickLoad => "Quick load",
+      Action::ExportScreen => "Export screen",
+      Action::ToggleClash => "Clash",
+      Action::CyclePalette => "Palette",
+      Action::ToggleDebug => "Debug",
+      Action::DebugUnlockPortal => "Unlock portal",
+      Action::DebugNextCavern => "Next cavern",
+      Action::DebugPreviousCavern => "Previous cavern",
+      Action::DebugAddScore => "Add score",
+      Action::DebugSlower => "Slower",
+      Action::DebugFaster => "Faster",
+      Action::DebugFreeze => "Freeze",
+      Action::DebugStep => "Step",
+      Action::DebugRewind => "Rewind",
+    }
+  }
+
+  /// The name of the setting that holds the action's bindings, after the
+  /// `key.` or `pad.` prefix.
+  fn setting(&self) -> &'static str {
+    match self {
+      Action::Left => "left",
+      Action::Right => "right",
+      Action::Jump => "jump",
+      Action::Pause => "pause",
+      Action::Mute => "mute",
+      Action::Quit => "quit",
+      Action::Start => "start",
+      Action::DefineKeys => "define_keys",
+      Action::ToggleInputMode => "input_mode",
+      Action::QuickSave => "quick_save",
+      Action::QuickLoad => "quick_load",
+      Action::ExportScreen => "export_screen",
+      Action::ToggleClash => "clash",
+      Action::CyclePalette => "palette",
+      Action::ToggleDebug => "debug",
+      Action::DebugUnlockPortal => "debug.unlock_portal",
+      Action::DebugNextCavern => "debug.next_cavern",
+      Action::DebugPreviousCavern => "debug.previous_cavern",
+      Action::DebugAddScore => "debug.add_score",
+      Action::DebugSlower => "debug.slower",
+      Action::DebugFaster => "debug.faster",
+      Action::DebugFreeze => "debug.freeze",
+      Action::DebugStep => "debug.step",
+      Action::DebugRewind => "debug.rewind",
+    }
+  }
+}
+
+/// A key or button that can be bound to an action.
+pub trait Binding: Copy + Eq + Hash + Send + Sync + 'static {
+  /// The prefix of the settings that hold these bindings.
+  const PREFIX: &'static str;
+
+  fn defaults(action: Action) -> Vec<Self>;
+
+  fn parse(name: &str) -> Option<Self>;
+
+  fn name(&self) -> String;
+}
+
+/// Parses the name of a variant of an enum with no fields, such as `Space` or
+/// `BracketLeft`.
+fn parse_variant<T: FromReflect + Typed>(name: &str) -> Option<T> {
+  // Converting an unknown variant panics, so check that there is one first.
+  match T::type_info() {
+    TypeInfo::Enum(info) if info.contains_variant(name) => {
+      T::from_reflect(&DynamicEnum::new(name, DynamicVariant::Unit))
+    }
+    _ => None,
+  }
+}
+
+impl Binding for KeyCode {
+  const PREFIX: &'static str = "key";
+
+  fn defaults(action: Action) -> Vec<Self> {
+    match action {
+      Action::Left => vec![KeyCode::Left, KeyCode::O],
+      Action::Right => vec![KeyCode::Right, KeyCode::P],
+      Action::Jump => vec![KeyCode::Space],
+      Action::Pause => vec![KeyCode::A],
+      Action::Mute => vec![KeyCode::H],
+      Action::Quit => vec![KeyCode::Escape],
+      Action::Start => vec![KeyCode::Return],
+      Action::DefineKeys => vec![KeyCode::R],
+      Action::ToggleInputMode => vec![KeyCode::Tab],
+      Action::QuickSave => vec![KeyCode::F5],
+      Action::QuickLoad => vec![KeyCode::F9],
+      Action::ExportScreen => vec![KeyCode::F12],
+      Action::ToggleClash => vec![KeyCode::C],
+      Action::CyclePalette => vec![KeyCode::K],
+      Action::ToggleDebug => vec![KeyCode::D],
+      Action::DebugUnlockPortal => vec![KeyCode::X],
+      Action::DebugNextCavern => vec![KeyCode::BracketRight],
+      Action::DebugPreviousCavern => vec![KeyCode::BracketLeft],
+      Action::DebugAddScore => vec![KeyCode::Apostrophe],
+      Action::DebugSlower => vec![KeyCode::Minus],
+      Action::DebugFaster => vec![KeyCode::Equals],
+      Action::DebugFreeze => vec![KeyCode::Backslash],
+      Action::DebugStep => vec![KeyCode::Period],
+      Action::DebugRewind => vec![KeyCode::Back],
+    }
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    parse_variant(name)
+  }
+
+  fn name(&self) -> String {
+    format!("{:?}", self)
+  }
+}
+
+/// How far a stick has to be pushed to count as pressed.
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// The names of the directions of the left stick, with the axis and whether
+/// it's the positive end.
+const STICK_DIRECTIONS: [(&str, GamepadAxisType, bool); 4] = [
+  ("StickLeft", GamepadAxisType::LeftStickX, false),
+  ("StickRight", GamepadAxisType::LeftStickX, true),
+  ("StickUp", GamepadAxisType::LeftStickY, true),
+  ("StickDown", GamepadAxisType::LeftStickY, false),
+];
+
+/// A gamepad button, or the left stick pushed in one direction. Pushing the
+/// stick is treated as pressing a button, so either can be bound to any
+/// action. Every connected gamepad is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PadInput {
+  Button(GamepadButtonType),
+  Stick(GamepadAxisType, bool),
+}
+
+impl Binding for PadInput {
+  const PREFIX: &'static str = "pad";
+
+  fn defaults(action: Action) -> Vec<Self> {
+    use GamepadButtonType::*;
+
+    let stick = |i: usize| PadInput::Stick(STICK_DIRECTIONS[i].1, STICK_DIRECTIONS[i].2);
+    let buttons = |buttons: &[GamepadButtonType]| buttons.iter().map(|b| PadInput::Button(*b)).collect();
+
+    match action {
+      Action::Left => vec![PadInput::Button(DPadLeft), stick(0)],
+      Action::Right => vec![PadInput::Button(DPadRight), stick(1)],
+      Action::Jump => buttons(&[South, East]),
+      Action::Pause | Action::Start => buttons(&[Start]),
+      Action::Mute => buttons(&[Select]),
+      Action::DefineKeys => buttons(&[West]),
+      Action::Quit => buttons(&[North]),
+      _ => vec![],
+    }
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    match STICK_DIRECTIONS.iter().find(|(n, _, _)| *n == name) {
+      Some((_, axis, positive)) => Some(PadInput::Stick(*axis, *positive)),
+      None => parse_variant(name).map(PadInput::Button),
+    }
+  }
+
+  fn name(&self) -> String {
+    match self {
+      PadInput::Button(button) => format!("{:?}", button),
+      PadInput::Stick(axis, positive) => STICK_DIRECTIONS
+        .iter()
+        .find(|(_, a, p)| a == axis && p == positive)
+        .map_or(format!("{:?}", axis), |(name, _, _)| (*name).to_owned()),
+    }
+  }
+}
+
+/// The keys or buttons that perform each action.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Bindings<T: Binding>(Vec<(Action, Vec<T>)>);
+
+pub type KeyBindings = Bindings<KeyCode>;
+pub type PadBindings = Bindings<PadInput>;
+
+impl<T: Binding> Default for Bindings<T> {
+  fn default() -> Self {
+    Bindings(Action::ALL.iter().map(|action| (*action, T::defaults(*action))).collect())
+  }
+}
+
+impl<T: Binding> Bindings<T> {
+  pub fn get(&self, action: Action) -> &[T] {
+    self.0.iter().find(|(a, _)| *a == action).map_or(&[], |(_, bindings)| bindings.as_slice())
+  }
+
+  pub fn set(&mut self, action: Action, bindings: Vec<T>) {
+    if let Some((_, b)) = self.0.iter_mut().find(|(a, _)| *a == action) {
+      *b = bindings;
+    }
+  }
+
+  /// The bindings for an action as shown to the player, e.g. `Left/O`.
+  pub fn describe(&self, action: Action) -> String {
+    let names: Vec<_> = self.get(action).iter().map(|b| b.name()).collect();
+    names.join("/")
+  }
+
+  /// Stores the bindings for every action in the config.
+  pub fn save_to(&self, config: &mut Config) {
+    for (action, bindings) in self.0.iter() {
+      let names: Vec<_> = bindings.iter().map(|b| b.name()).collect();
+      config.set(&setting::<T>(*action), names.join(" "));
+    }
+  }
+
+  fn pressed(&self, input: &Input<T>, action: Action) -> bool {
+    input.any_pressed(self.get(action).iter().copied())
+  }
+
+  fn just_pressed(&self, input: &Input<T>, action: Action) -> bool {
+    input.any_just_pressed(self.get(action).iter().copied())
+  }
+
+  fn just_released(&self, input: &Input<T>, action: Action) -> bool {
+    self.get(action).iter().any(|b| input.just_released(*b))
+  }
+}
+
+fn setting<T: Binding>(action: Action) -> String {
+  format!("{}.{}", T::PREFIX, action.setting())
+}
+
+/// Reads the bindings from the config, keeping the defaults for any action
+/// that's missing or has names that can't be read.
+impl<T: Binding> From<&Config> for Bindings<T> {
+  fn from(config: &Config) -> Self {
+    let mut bindings = Bindings::default();
+
+    for action in Action::ALL {
+      let setting = setting::<T>(action);
+      if let Some(value) = config.get(&setting) {
+        match value.split_whitespace().map(T::parse).collect::<Option<Vec<_>>>() {
+          Some(b) => bindings.set(action, b),
+          None => eprintln!("Error: unknown key or button in {} = {}", setting, value),
+        }
+      }
+    }
+
+    bindings
+  }
+}
+
+fn load_bindings(mut commands: Commands, config: Res<Config>) {
+  commands.insert_resource(KeyBindings::from(&*config));
+  commands.insert_resource(PadBindings::from(&*config));
+  commands.insert_resource(InputMode::from(&*config));
+}
+
+fn report_gamepads(mut events: EventReader<GamepadConnectionEvent>) {
+  for event in events.iter() {
+    match &event.connection {
+      GamepadConnection::Connected(info) => info!("Gamepad {} connected: {}", event.gamepad.id, info.name),
+      GamepadConnection::Disconnected => info!("Gamepad {} disconnected", event.gamepad.id),
+    }
+  }
+}
+
+/// Works out which gamepad buttons and stick directions are held on any
+/// connected gamepad. Anything held on a gamepad that's unplugged is released.
+fn update_pad_input(
+  gamepads: Res<Gamepads>,
+  buttons: Res<Input<GamepadButton>>,
+  axes: Res<Axis<GamepadAxis>>,
+  mut input: ResMut<Input<PadInput>>,
+) {
+  let mut held: Vec<PadInput> = buttons
+    .get_pressed()
+    .filter(|button| gamepads.contains(button.gamepad))
+    .map(|button| PadInput::Button(button.button_type))
+    .collect();
+
+  for gamepad in gamepads.iter() {
+    for (_, axis, positive) in STICK_DIRECTIONS {
+      let value = axes.get(GamepadAxis::new(gamepad, axis)).unwrap_or(0.);
+      if (if positive { value } else { -value }) > STICK_THRESHOLD {
+        held.push(PadInput::Stick(axis, positive));
+      }
+    }
+  }
+
+  input.clear();
+  let released: Vec<_> = input.get_pressed().filter(|i| !held.contains(i)).copied().collect();
+  for i in released {
+    input.release(i);
+  }
+  for i in held {
+    input.press(i);
+  }
+}
+
+/// Checks whether actions are being performed, on the keyboard or on any
+/// gamepad.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+  keys: Res<'w, Input<KeyCode>>,
+  key_bindings: Res<'w, KeyBindings>,
+  pad: Res<'w, Input<PadInput>>,
+  pad_bindings: Res<'w, PadBindings>,
+  mode: Res<'w, InputMode>,
+}
+
+impl Actions<'_> {
+  pub fn pressed(&self, action: Action) -> bool {
+    let keyboard = match self.authentic(action) {
+      Some((_, now)) => now,
+      None => self.key_bindings.pressed(&self.keys, action),
+    };
+    keyboard || self.pad_bindings.pressed(&self.pad, action)
+  }
+
+  pub fn just_pressed(&self, action: Action) -> bool {
+    let keyboard = match self.authentic(action) {
+      Some((before, now)) => now && !before,
+      None => self.key_bindings.just_pressed(&self.keys, action),
+    };
+    keyboard || self.pad_bindings.just_pressed(&self.pad, action)
+  }
+
+  pub fn just_released(&self, action: Action) -> bool {
+    let keyboard = match self.authentic(action) {
+      Some((before, now)) => before && !now,
+      None => self.key_bindings.just_released(&self.keys, action),
+    };
+    keyboard || self.pad_bindings.just_released(&self.pad, action)
+  }
+
+  /// Whether the action was performed on the previous frame and now, if it's
+  /// read from the Spectrum keyboard matrix.
+  fn authentic(&self, action: Action) -> Option<(bool, bool)> {
+    if *self.mode != InputMode::Authentic {
+      return None;
+    }
+
+    let now = Matrix::current(&self.keys).action(action)?;
+    Matrix::previous(&self.keys).action(action).map(|before| (before, now))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_keys_from_config() -> anyhow::Result<()> {
+    let config = Config::try_from("key.left = Q BracketLeft\nkey.jump = Nope\n")?;
+    let bindings = KeyBindings::from(&config);
+
+    assert_eq!(bindings.get(Action::Left), &[KeyCode::Q, KeyCode::BracketLeft]);
+    assert_eq!(bindings.get(Action::Jump), &[KeyCode::Space]);
+    assert_eq!(bindings.describe(Action::Right), "Right/P");
+
+    let mut saved = Config::default();
+    bindings.save_to(&mut saved);
+    assert_eq!(saved.get("key.left"), Some("Q BracketLeft"));
+    assert_eq!(KeyBindings::from(&saved), bindings);
+
+    Ok(())
+  }
+
+  #[test]
+  fn default_keys_are_all_different() {
+    let bindings = KeyBindings::default();
+    let keys: Vec<_> = Action::ALL.iter().flat_map(|action| bindings.get(*action)).collect();
+
+    for (i, key) in keys.iter().enumerate() {
+      assert!(!keys[i + 1..].contains(key), "{:?} is bound twice", key);
+    }
+  }
+
+  #[test]
+  fn reads_gamepad_buttons_and_stick_from_config() -> anyhow::Result<()> {
+    let config = Config::try_from("pad.jump = North StickUp\n")?;
+    let bindings = PadBindings::from(&config);
+
+    assert_eq!(
+      bindings.get(Action::Jump),
+      &[PadInput::Button(GamepadButtonType::North), PadInput::Stick(GamepadAxisType::LeftStickY, true)]
+    );
+    assert_eq!(bindings.describe(Action::Left), "DPadLeft/StickLeft");
+
+    let mut saved = Config::default();
+    bindings.save_to(&mut saved);
+    assert_eq!(saved.get("pad.jump"), Some("North StickUp"));
+    assert_eq!(PadBindings::from(&saved), bindings);
+
+    Ok(())
+  }
+}
@@ -11,7 +11,7 @@ pub enum Direction {
   Right,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Debug)]
 pub struct HorizontalMotion {
   pub walking: bool,
   pub current_frame: usize,
@@ -69,6 +69,10 @@ impl HorizontalMotion {
 }
 
 
+/// How many animation frames an actor has: four facing right, then four
+/// facing left.
+pub const FRAME_COUNT: usize = 8;
+
 #[derive(Component)]
 pub struct Sprites {
   pub images: Vec<Handle<Image>>,
@@ -1,7 +1,7 @@
 use crate::color::ColorName;
 use bevy::prelude::*;
 
-use crate::text::{Text, TextAttributes};
+use crate::text::{udg, CharsetResource, Text, TextAttributes};
 
 /// Adds the air supply bar that shows how much time Willy has left until he runs out of air.
 pub struct AirPlugin;
@@ -9,23 +9,32 @@ pub struct AirPlugin;
 impl Plugin for AirPlugin {
   fn build(&self, app: &mut App) {
     app.add_systems(Startup, setup);
+    app.add_systems(PostStartup, define_air_bar);
   }
 }
 
-fn setup(mut commands: Commands) {
-  // Red handlebar
-  commands.spawn(Text::new(
-    "AIR       ",
-    (0, 17),
-    &TextAttributes::new_bright(ColorName::White, ColorName::Red),
-  ));
+/// The user-defined graphic that the air bar is drawn with.
+const AIR_BAR_UDG: usize = 0;
 
-  // Green handlebar
-  commands.spawn(Text::new(
-    "                       ",
-    (9, 17),
-    &TextAttributes::new_bright(ColorName::White, ColorName::Green),
-  ));
+/// Each cell of the air bar has four rows of ink through the middle.
+pub const AIR_BAR_GLYPH: [u8; 8] = [0, 0, 0xff, 0xff, 0xff, 0xff, 0, 0];
+
+/// The first cell and number of cells of the air bar when the air supply is
+/// full.
+pub const AIR_BAR_START: u8 = 4;
+pub const AIR_BAR_LENGTH: usize = 28;
+
+fn setup(mut commands: Commands) {
+  // Red handlebar, with the rest of the row green
+  let bar = udg(AIR_BAR_UDG).to_string().repeat(AIR_BAR_LENGTH);
+  commands.spawn(
+    Text::new(
+      &format!("AIR {}", bar),
+      (0, 17),
+      &TextAttributes::new_bright(ColorName::White, ColorName::Red),
+    )
+    .with_span(10..32, &TextAttributes::new_bright(ColorName::White, ColorName::Green)),
+  );
 
   // Black separator bar
   commands.spawn(Text::new(
@@ -34,3 +43,7 @@ fn setup(mut commands: Commands) {
     &TextAttributes::new_bright(ColorName::Black, ColorName::Black),
   ));
 }
+
+fn define_air_bar(mut charset: ResMut<CharsetResource>) {
+  charset.define_udg(AIR_BAR_UDG, AIR_BAR_GLYPH);
+}
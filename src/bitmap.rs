@@ -1,6 +1,6 @@
 use std::cmp::Ordering;
 
-use crate::color::Attributes;
+use crate::{color::Attributes, palette::Palette};
 use bevy::{
   prelude::Image,
   render::render_resource::{Extent3d, TextureDimension, TextureFormat},
@@ -10,7 +10,7 @@ use bevy::{
 /// and an optional SpectrumColor (attribute data). It can be
 /// converted into an image, and the attributes can be changed
 /// at that time.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Bitmap {
   data: Vec<u8>,
   width: usize,
@@ -45,17 +45,21 @@ impl Bitmap {
     }
   }
 
-  /// Renders this bitmap as an image using its prefered color.
-  /// WARNING: If no color is defined, this will panic.
-  pub fn render(&self) -> Image {
-    self.render_with_color(self.color.as_ref().unwrap())
+  pub fn width(&self) -> usize {
+    self.width
   }
 
-  fn render_to_rgba(&self, color: &Attributes) -> Vec<u8> {
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Renders this bitmap as rgba pixel data using the given color attributes,
+  /// one row of pixels after another.
+  pub fn render_to_rgba(&self, color: &Attributes, palette: &Palette) -> Vec<u8> {
     let mut rgba = Vec::with_capacity(self.width * self.height * 4);
 
-    let ink_color = color.ink_rgba();
-    let paper_color = color.paper_rgba();
+    let ink_color = color.ink_rgba(palette);
+    let paper_color = color.paper_rgba(palette);
 
     for b in self.data.iter() {
       to_rgba(&mut rgba, b, &ink_color, &paper_color);
@@ -65,8 +69,22 @@ impl Bitmap {
   }
 
   /// Renders this sprite to a bevy image using the given color attributes.
-  pub fn render_with_color(&self, color: &Attributes) -> Image {
-    let data = self.render_to_rgba(color);
+  pub fn render_with_color(&self, color: &Attributes, palette: &Palette) -> Image {
+    self.to_image(self.render_to_rgba(color, palette))
+  }
+
+  /// Renders this bitmap to a bevy image with white ink and a transparent
+  /// background, so that a sprite's color can tint it to any ink color.
+  pub fn render_mask(&self) -> Image {
+    let mut rgba = Vec::with_capacity(self.width * self.height * 4);
+    for b in self.data.iter() {
+      to_rgba(&mut rgba, b, &[0xff; 4], &[0; 4]);
+    }
+
+    self.to_image(rgba)
+  }
+
+  fn to_image(&self, data: Vec<u8>) -> Image {
     Image::new(
       Extent3d {
         width: self.width as u32,
@@ -100,6 +118,26 @@ impl Bitmap {
     }
   }
 
+  /// The raw pixel data, one bit per pixel, with rows stored one after another.
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  /// Returns true if the pixel at the given position is set (i.e. ink).
+  pub fn get_pixel(&self, (x, y): (usize, usize)) -> bool {
+    self.data[y * self.width / 8 + x / 8] & (0b10000000 >> (x % 8)) != 0
+  }
+
+  pub fn set_pixel(&mut self, (x, y): (usize, usize), value: bool) {
+    let mask = 0b10000000 >> (x % 8);
+    let byte = &mut self.data[y * self.width / 8 + x / 8];
+    if value {
+      *byte |= mask;
+    } else {
+      *byte &= !mask;
+    }
+  }
+
   //  Rotates one row in this bitmap by `pixels` pixels (this can be negative to rotate left)
   pub fn rotate_row(&mut self, row: u8, pixels: i8) {
     // TODO: support images with width > 8.
@@ -0,0 +1,186 @@
+//! A bot that plays through caverns on its own, avoiding guardians, and records
+//! what it did as a replay. This runs entirely without a window, using
+//! [crate::simulation], so it can be used to check that changes to how Willy
+//! moves don't make any caverns impossible to complete.
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+
+use crate::{
+  gamedata::cavern::Cavern,
+  reachability,
+  simulation::{self, Guardians, Input},
+};
+
+/// A sequence of inputs, one per game tick, that plays through a cavern.
+///
+/// Replays are saved as text: a line naming the cavern number, followed by the
+/// inputs in the format produced by [simulation::describe_route]. E.g.
+///
+/// ```text
+/// cavern 0
+/// R*50 J -*10 JR -*15
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct Replay {
+  pub cavern: usize,
+  pub inputs: Vec<Input>,
+}
+
+impl Replay {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    Replay::try_from(fs::read_to_string(path)?.as_str())
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    fs::write(path, self.to_string())?;
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for Replay {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "cavern {}", self.cavern)?;
+    writeln!(f, "{}", simulation::describe_route(&self.inputs))
+  }
+}
+
+impl TryFrom<&str> for Replay {
+  type Error = anyhow::Error;
+
+  fn try_from(s: &str) -> Result<Self> {
+    let (header, inputs) = s.split_once('\n').unwrap_or((s, ""));
+    let cavern = header
+      .strip_prefix("cavern ")
+      .ok_or_else(|| anyhow::anyhow!("Expected replay to start with `cavern <number>`"))?
+      .trim()
+      .parse()?;
+
+    Ok(Replay {
+      cavern,
+      inputs: simulation::parse_route(inputs)?,
+    })
+  }
+}
+
+/// How many states the bot will explore in total while looking for a route
+/// through a cavern, before giving up.
+pub const MAX_PLANNING_NODES: usize = 40_000_000;
+
+/// Plays through the given cavern, returning a replay that completes it if the
+/// bot can find one.
+pub fn play(number: usize, cavern: &Cavern) -> Option<Replay> {
+  // There's no point searching with guardians if there's no way through even
+  // without them.
+  if !reachability::analyse(cavern).is_solvable() {
+    return None;
+  }
+
+  let inputs = simulation::plan_route(cavern, &Guardians::from(cavern), MAX_PLANNING_NODES)?;
+
+  Some(Replay {
+    cavern: number,
+    inputs,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    gamedata::{GameData, CAVERN_COUNT},
+    simulation::Outcome,
+  };
+
+  /// The caverns that can't be completed yet, and why. Every other cavern has
+  /// a replay in `replays/` that completes it. `cargo run -- analyse N` shows
+  /// what can't be reached in a cavern. As more of the original game's
+  /// behaviour is implemented, this should shrink to nothing.
+  const KNOWN_GAPS: [(usize, &str); 8] = [
+    (7, "the walls only open with the switches, which aren't implemented yet"),
+    (8, "`analyse` finds a route, but the bot can't find one past the guardians"),
+    (11, "it needs the switches, and the bot runs for over ten minutes without a route"),
+    (13, "the item at (31, 15) can't be reached, and the Skylabs aren't implemented yet"),
+    (14, "the item at (12, 6) can't be reached"),
+    (15, "three of the items and the portal can't be reached"),
+    (17, "the item at (16, 1) and the portal can't be reached"),
+    (18, "the item at (1, 5) and the portal can't be reached"),
+  ];
+
+  /// The caverns that should have a replay that completes them.
+  fn completable_caverns() -> impl Iterator<Item = usize> {
+    (0..CAVERN_COUNT).filter(|number| KNOWN_GAPS.iter().all(|(gap, _)| gap != number))
+  }
+
+  fn replay_path(number: usize) -> String {
+    format!("replays/cavern-{:02}.replay", number)
+  }
+
+  #[test]
+  fn replays_round_trip_through_text() -> Result<()> {
+    let replay = Replay {
+      cavern: 3,
+      inputs: vec![Input::Right, Input::Right, Input::Jump, Input::Idle],
+    };
+
+    assert_eq!(replay.to_string(), "cavern 3\nR*2 J -\n");
+    assert_eq!(Replay::try_from(replay.to_string().as_str())?, replay);
+
+    Ok(())
+  }
+
+  #[test]
+  fn original_caverns_remain_completable() -> Result<()> {
+    let game_data = GameData::load("assets/ManicMiner.bin")?;
+
+    for number in completable_caverns() {
+      let replay = Replay::load(replay_path(number))?;
+      let cavern = &game_data.caverns[replay.cavern];
+
+      assert!(
+        matches!(
+          simulation::play(cavern, &replay.inputs),
+          Outcome::Completed { .. }
+        ),
+        "Saved replay for cavern {} doesn't complete it",
+        number
+      );
+    }
+
+    // A cavern that's been fixed should come off the list of gaps.
+    for (number, reason) in KNOWN_GAPS {
+      assert!(
+        !Path::new(&replay_path(number)).exists(),
+        "Cavern {} has a replay, but is listed as a gap because {}",
+        number,
+        reason
+      );
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  #[ignore = "slow without optimisations, run with `cargo test --release -- --ignored`"]
+  fn bot_finds_fresh_routes_through_completable_caverns() -> Result<()> {
+    let game_data = GameData::load("assets/ManicMiner.bin")?;
+
+    for number in completable_caverns() {
+      let cavern = &game_data.caverns[number];
+      let replay =
+        play(number, cavern).unwrap_or_else(|| panic!("Couldn't complete cavern {}", number));
+
+      assert!(
+        matches!(
+          simulation::play(cavern, &replay.inputs),
+          Outcome::Completed { .. }
+        ),
+        "Replay for cavern {} doesn't complete it",
+        number
+      );
+    }
+
+    Ok(())
+  }
+}
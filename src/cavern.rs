@@ -1,10 +1,13 @@
+use crate::actions::{Action, Actions};
 use crate::bitmap::Bitmap;
 use crate::color::ColorName;
 use crate::{clamp, despawn_all};
-use crate::gamedata::cavern::{CavernTileType, Conveyor, ConveyorDirection};
+use crate::gamedata::cavern::{Cavern, CavernTileType, Conveyor, ConveyorDirection};
+use crate::image_cache::{ImageTransform, SpectrumImages};
+use crate::palette::Palette;
 use crate::position::{Layer, Position, Relative};
-use crate::timer::GameTimer;
-use crate::willy::Willy;
+use crate::timer::GameTick;
+use crate::willy::{MoveWilly, Willy};
 use crate::{
   gamedata::GameDataResource,
   handle_errors,
@@ -16,6 +19,10 @@ use bevy::{prelude::*, sprite::Anchor};
 /// Adds drawing the current cavern
 pub struct CavernPlugin;
 
+/// The cavern being played. Whenever this is changed, even to the same
+/// number, the cavern is set up afresh, with its crumbling floors restored.
+/// That's how a quick load restarts a cavern, and losing a life should do the
+/// same once Willy can die.
 #[derive(Resource, Debug)]
 pub struct CurrentCavern {
   pub number: usize,
@@ -29,19 +36,66 @@ struct CavernTile {
 
 /// The current state of the cavern. This can be used by other plugins to query information
 /// about the tiles surrounding Willy.
-#[derive(Resource, Debug)]
+#[derive(Resource, Debug, Clone)]
 pub struct CavernState {
   tile_types: [[CavernTileType; 16]; 32],
   crumble_level: [[u8; 16]; 32],
 }
 
 impl CavernState {
+  /// The type of the tile at a cell. Cells outside the cavern are empty.
   pub fn get_tile_type(&self, (x, y): (u8, u8)) -> CavernTileType {
-    self.tile_types[x as usize][y as usize]
+    self.tile_types
+      .get(x as usize)
+      .and_then(|column| column.get(y as usize))
+      .copied()
+      .unwrap_or(CavernTileType::Background)
   }
 
   pub fn get_crumble_level(&self, (x, y): (u8, u8)) -> u8 {
-    self.crumble_level[x as usize][y as usize]
+    self.crumble_level.get(x as usize).and_then(|column| column.get(y as usize)).copied().unwrap_or(7)
+  }
+
+  /// The crumbling floors that have started to crumble, with how far they've
+  /// got, or None for those that have crumbled away.
+  pub fn crumbled_floors(&self) -> Vec<((u8, u8), Option<u8>)> {
+    let mut floors = Vec::new();
+    for y in 0..16 {
+      for x in 0..32 {
+        let level = self.crumble_level[x][y];
+        if level < 7 {
+          let gone = self.tile_types[x][y] != CavernTileType::CrumblingFloor;
+          floors.push(((x as u8, y as u8), if gone { None } else { Some(level) }));
+        }
+      }
+    }
+    floors
+  }
+
+  /// Sets how far a crumbling floor has crumbled, as returned by
+  /// [CavernState::crumbled_floors].
+  pub fn set_crumbled_floor(&mut self, (x, y): (u8, u8), level: Option<u8>) {
+    let (x, y) = (x as usize, y as usize);
+    self.crumble_level[x][y] = level.unwrap_or(0);
+    if level.is_none() {
+      self.tile_types[x][y] = CavernTileType::Background;
+    }
+  }
+
+  /// Crumbles the floors under Willy's feet by a stage, as the original does on
+  /// every tick that starts with him standing on them. A floor sinks a row at
+  /// each stage, and gives way once it's sunk all eight.
+  pub fn crumble_under(&mut self, position: &Position) {
+    for (x, y) in position.relative(Relative::Below) {
+      if self.get_tile_type((x, y)) == CavernTileType::CrumblingFloor {
+        let (x, y) = (x as usize, y as usize);
+        if self.crumble_level[x][y] == 0 {
+          self.tile_types[x][y] = CavernTileType::Background;
+        } else {
+          self.crumble_level[x][y] -= 1;
+        }
+      }
+    }
   }
 
   pub fn is_type(&self, position: &Position, relative: Relative, kind: CavernTileType) -> bool {
@@ -54,11 +108,48 @@ impl CavernState {
   }
 }
 
+impl From<&Cavern> for CavernState {
+  fn from(cavern: &Cavern) -> Self {
+    let mut state = CavernState {
+      tile_types: [[CavernTileType::Background; 16]; 32],
+      crumble_level: [[7; 16]; 32],
+    };
+
+    for y in 0..16 {
+      for x in 0..32 {
+        state.tile_types[x][y] = cavern
+            .get_bg_sprite_index((x as u8, y as u8))
+            .unwrap_or(0).into();
+      }
+    }
+
+    state
+  }
+}
+
+#[cfg(test)]
+impl CavernState {
+  /// An empty cavern with just the given tiles in it, for tests.
+  pub fn with_tiles(tiles: &[((u8, u8), CavernTileType)]) -> Self {
+    let mut state = CavernState {
+      tile_types: [[CavernTileType::Background; 16]; 32],
+      crumble_level: [[7; 16]; 32],
+    };
+    for ((x, y), tile_type) in tiles {
+      state.tile_types[*x as usize][*y as usize] = *tile_type;
+    }
+    state
+  }
+}
+
 #[derive(Component)]
 struct CavernName;
 
 impl Plugin for CavernPlugin {
   fn build(&self, app: &mut bevy::prelude::App) {
+    // The cavern is known before startup, so that everything can be spawned
+    // in it.
+    app.insert_resource(CurrentCavern { number: 0 });
     app.add_systems(Startup, setup);
     app.add_systems(
       Update,
@@ -68,39 +159,40 @@ impl Plugin for CavernPlugin {
         spawn_cavern.pipe(handle_errors),
         check_debug_keyboard,
         update_tile_state,
-        update_crumble,
         update_tile_sprites,
         update_conveyor_images,
-        move_conveyor
       ),
     );
+    app.add_systems(FixedUpdate, (update_crumble.before(MoveWilly), move_conveyor).in_set(GameTick));
   }
 }
 
+/// Images of a crumbling floor at each crumble level, from 0 (sunk seven
+/// rows) to 7 (untouched), and of the background that's left once it's gone.
 #[derive(Resource)]
 struct CrumblingTileImages {
-  images: Vec<Handle<Image>>
+  images: Vec<Handle<Image>>,
+  gone: Handle<Image>,
 }
 
 impl CrumblingTileImages {
   fn new() -> Self {
     Self {
-      images: Vec::with_capacity(7)
+      images: Vec::with_capacity(8),
+      gone: Handle::default(),
     }
   }
 
-  fn update(&mut self, mut image_assets: ResMut<Assets<Image>>, base_bitmap: &Bitmap) {
-    self.images.clear();
-    let mut new_bitmap = base_bitmap.shift_down();
-    for _ in 0..8 {
-      self.images.push(image_assets.add(new_bitmap.render()));
-      new_bitmap = new_bitmap.shift_down();
-    }
+  fn update(&mut self, image_assets: &mut SpectrumImages, base_bitmap: &Bitmap, background: &Bitmap) {
+    let color = base_bitmap.color.as_ref().unwrap();
+    self.images = (0..8)
+      .map(|level| image_assets.get_transformed(base_bitmap, color, ImageTransform::ShiftDown(7 - level)))
+      .collect();
+    self.gone = image_assets.get(background, background.color.as_ref().unwrap());
   }
 }
 
 fn setup(mut commands: Commands) {
-  commands.insert_resource(CurrentCavern { number: 0 });
   commands.insert_resource(CavernState {
     tile_types: [[CavernTileType::Background; 16]; 32],
     crumble_level: [[7; 16]; 32]
@@ -122,15 +214,19 @@ fn setup(mut commands: Commands) {
 fn update_border(
   game_data: Res<GameDataResource>,
   cavern: Res<CurrentCavern>,
+  palette: Res<Palette>,
   mut clear_color: ResMut<ClearColor>,
 ) {
-  if cavern.is_changed() {
-    let cavern = &game_data.caverns[cavern.number];
-    let border_color = cavern.border_color.ink_color();
-    clear_color.0 = border_color;
+  if cavern.is_changed() || palette.is_changed() {
+    clear_color.0 = border_color(&game_data, &cavern, &palette);
   }
 }
 
+/// The color of the border around the current cavern.
+pub fn border_color(game_data: &GameDataResource, cavern: &CurrentCavern, palette: &Palette) -> Color {
+  game_data.caverns[cavern.number].border_color.ink_color(palette)
+}
+
 fn update_cavern_name(
   game_data: Res<GameDataResource>,
   cavern: Res<CurrentCavern>,
@@ -146,7 +242,7 @@ fn spawn_cavern(
   mut commands: Commands,
   cavern: Res<CurrentCavern>,
   game_data: Res<GameDataResource>,
-  mut images: ResMut<Assets<Image>>,
+  mut images: SpectrumImages,
   mut crumbling_tiles: ResMut<CrumblingTileImages>,
   query: Query<Entity, With<CavernTile>>
 ) -> Result<()> {
@@ -159,11 +255,11 @@ fn spawn_cavern(
     // Create images for the tiles in this cavern so we can spawn sprites for them
     let mut image_handles = Vec::new();
     for tile in cavern.tile_bitmaps.iter() {
-      image_handles.push(images.add(tile.render()));
+      image_handles.push(images.get(tile, tile.color.as_ref().unwrap()));
     }
 
     let crumbling_bitmap = &cavern.tile_bitmaps[2]; // TODO: don't hardcode this
-    crumbling_tiles.update(images, crumbling_bitmap);
+    crumbling_tiles.update(&mut images, crumbling_bitmap, &cavern.tile_bitmaps[0]);
 
 
     for y in 0..16 {
@@ -190,10 +286,10 @@ fn spawn_cavern(
   Ok(())
 }
 
-fn check_debug_keyboard(keys: Res<Input<KeyCode>>, mut cavern: ResMut<CurrentCavern>) {
-  if keys.just_released(KeyCode::BracketRight) && cavern.number < 19 {
+fn check_debug_keyboard(actions: Actions, mut cavern: ResMut<CurrentCavern>) {
+  if actions.just_released(Action::DebugNextCavern) && cavern.number < 19 {
     cavern.number += 1;
-  } else if keys.just_released(KeyCode::BracketLeft) && cavern.number > 0 {
+  } else if actions.just_released(Action::DebugPreviousCavern) && cavern.number > 0 {
     cavern.number -= 1;
   }
 }
@@ -202,41 +298,19 @@ fn update_tile_state(mut cavern_state: ResMut<CavernState>,
   cavern: Res<CurrentCavern>, game_data: Res<GameDataResource>,
 ) {
   if cavern.is_changed() {
-    let current_cavern = cavern.number;
-    let cavern = &game_data.caverns[current_cavern];
-
-    for y in 0..16 {
-      for x in 0..32 {
-        cavern_state.tile_types[x][y] = cavern
-            .get_bg_sprite_index((x as u8, y as u8))
-            .unwrap_or(0).into();
-        cavern_state.crumble_level[x][y] = 7;
-      }
-    }
+    *cavern_state = CavernState::from(&game_data.caverns[cavern.number]);
   }
 }
 
-// If willy is standing on a crumbling tile, decrement its crumble
-// animation, update its sprite. If it exceeds the last animation
-// frame, change the tile type to Background so that Willy will fall.
-fn update_crumble(timer: Res<GameTimer>, mut cavern_state: ResMut<CavernState>,
+/// Crumbles the floor under Willy if he's standing on it. This runs before he
+/// moves, so floors crumble on the tick he jumps off them or walks away, but
+/// not on the tick he lands.
+fn update_crumble(mut cavern_state: ResMut<CavernState>,
   query: Query<(&Position, &Willy)>,
 ) {
-  if timer.just_finished() {
-    for (position, willy) in query.iter() {
-      if !willy.airborne_status.is_airborne() {
-        for (cx, cy) in position.relative(Relative::Below) {
-          if matches!(cavern_state.get_tile_type((cx, cy)), CavernTileType::CrumblingFloor) {
-            let level = cavern_state.crumble_level[cx as usize][cy as usize];
-            if level == 0 {
-              cavern_state.tile_types[cx as usize][cy as usize] = CavernTileType::Background;
-            } else {
-              cavern_state.crumble_level[cx as usize][cy as usize] -= 1;
-            }
-          }
-        }
-
-      }
+  for (position, willy) in query.iter() {
+    if !willy.airborne_status.is_airborne() {
+      cavern_state.crumble_under(position);
     }
   }
 }
@@ -244,37 +318,49 @@ fn update_crumble(timer: Res<GameTimer>, mut cavern_state: ResMut<CavernState>,
 fn update_tile_sprites(crumbling_images: Res<CrumblingTileImages>,
     cavern_state: Res<CavernState>, mut query: Query<(&CavernTile, &mut Handle<Image>)>) {
   if cavern_state.is_changed() {
+    // This goes by the tile the cavern started with, so that floors that have
+    // crumbled away can be restored when rewinding.
     for (tile, mut image) in query.iter_mut() {
-      if matches!(cavern_state.get_tile_type(tile.pos), CavernTileType::CrumblingFloor) {
-        let crumble_level = cavern_state.get_crumble_level(tile.pos);
-        if crumble_level < 7 {
-          *image = crumbling_images.images[(7 - crumble_level) as usize].clone();
-        }
+      if tile.tile_type == CavernTileType::CrumblingFloor {
+        *image = if cavern_state.get_tile_type(tile.pos) == CavernTileType::CrumblingFloor {
+          crumbling_images.images[cavern_state.get_crumble_level(tile.pos) as usize].clone()
+        } else {
+          crumbling_images.gone.clone()
+        };
       }
     }
   }
 }
 
-fn update_conveyor_images(cavern: Res<CurrentCavern>, images: ResMut<Assets<Image>>, game_data: Res<GameDataResource>,
+fn update_conveyor_images(cavern: Res<CurrentCavern>, mut images: SpectrumImages, game_data: Res<GameDataResource>,
     mut conveyor_images: ResMut<ConveyorImages>) {
   if cavern.is_changed() {
     let cavern = &game_data.caverns[cavern.number];
-    *conveyor_images = ConveyorImages::new(images, &cavern.tile_bitmaps[4], &cavern.conveyor);
+    *conveyor_images = ConveyorImages::new(&mut images, &cavern.tile_bitmaps[4], &cavern.conveyor);
   }
 }
 
-fn move_conveyor(timer: Res<GameTimer>, mut conveyor_images: ResMut<ConveyorImages>,
+fn move_conveyor(mut conveyor_images: ResMut<ConveyorImages>,
     mut query: Query<(&CavernTile, &mut Handle<Image>)>) {
-  if timer.just_finished() {
-    conveyor_images.conveyor_frame = clamp(conveyor_images.conveyor_frame + 1, 0, 7);
+  let last_frame = conveyor_images.images.len().saturating_sub(1);
+  conveyor_images.conveyor_frame = clamp(conveyor_images.conveyor_frame + 1, 0, last_frame);
 
-    for (tile, mut image) in query.iter_mut() {
-      if tile.tile_type == CavernTileType::Conveyor {
-        *image = conveyor_images.images[conveyor_images.conveyor_frame].clone();
-      }
+  for (tile, mut image) in query.iter_mut() {
+    if tile.tile_type == CavernTileType::Conveyor {
+      *image = conveyor_images.images[conveyor_images.conveyor_frame].clone();
     }
+  }
+}
 
+/// The animation frames for a conveyor going the given way, moving its top row
+/// on each tick until the pattern repeats. A conveyor that doesn't move only
+/// has the one frame.
+fn conveyor_transforms(direction: ConveyorDirection) -> Vec<ImageTransform> {
+  let speed = direction.surface_speed();
+  if speed == 0 {
+    return vec![ImageTransform::None];
   }
+  (0..8 / speed.abs()).map(|frame| ImageTransform::Conveyor(frame * speed)).collect()
 }
 
 /// Images for animating conveyors
@@ -290,25 +376,54 @@ impl ConveyorImages {
     ConveyorImages { images: vec![], conveyor_frame: 0 }
   }
 
-  fn new(mut image_assets: ResMut<Assets<Image>>, conveyor_bitmap: &Bitmap, conveyor: &Conveyor) -> Self {
-    let mut images = Vec::new();
-    images.push(image_assets.add(conveyor_bitmap.render()));
-
-    let top_direction = if matches!(conveyor.direction, ConveyorDirection::Left) { -1 } else { 1 };
-
-    let mut new_bitmap = conveyor_bitmap.clone();
-    for _ in 0..7 {
-      new_bitmap.rotate_row(0, top_direction);
-      new_bitmap.rotate_row(2, -top_direction);
-
-      images.push(image_assets.add(new_bitmap.render()));
-      new_bitmap = new_bitmap.clone();
-    }
-
+  fn new(image_assets: &mut SpectrumImages, conveyor_bitmap: &Bitmap, conveyor: &Conveyor) -> Self {
+    let color = conveyor_bitmap.color.as_ref().unwrap();
+    let images = conveyor_transforms(conveyor.direction)
+      .into_iter()
+      .map(|transform| image_assets.get_transformed(conveyor_bitmap, color, transform))
+      .collect();
 
     Self {
       conveyor_frame: 0,
       images
     }
   }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn floors_crumble_in_eight_stages() {
+    // Willy's feet cover cells 10 and 11, so the floor at 12 is just beside
+    // him.
+    let mut cavern_state = CavernState::with_tiles(&[
+      ((10, 10), CavernTileType::CrumblingFloor),
+      ((12, 10), CavernTileType::CrumblingFloor),
+    ]);
+    let willy = Position::at_char_pos(Layer::Characters, (10, 8));
+
+    for level in (0..7).rev() {
+      cavern_state.crumble_under(&willy);
+      assert_eq!(cavern_state.get_crumble_level((10, 10)), level);
+    }
+    assert_eq!(cavern_state.get_tile_type((10, 10)), CavernTileType::CrumblingFloor);
+    cavern_state.crumble_under(&willy);
+    assert_eq!(cavern_state.get_tile_type((10, 10)), CavernTileType::Background);
+    assert_eq!(cavern_state.crumbled_floors(), [((10, 10), None)]);
+
+    // Only the floor he's on crumbles.
+    assert_eq!(cavern_state.get_tile_type((12, 10)), CavernTileType::CrumblingFloor);
+    assert_eq!(cavern_state.get_crumble_level((12, 10)), 7);
+  }
+
+  #[test]
+  fn conveyors_animate_the_way_they_move() {
+    use ImageTransform::Conveyor as Frame;
+
+    assert_eq!(conveyor_transforms(ConveyorDirection::Left), [Frame(0), Frame(-2), Frame(-4), Frame(-6)]);
+    assert_eq!(conveyor_transforms(ConveyorDirection::Right), [Frame(0), Frame(2), Frame(4), Frame(6)]);
+    assert_eq!(conveyor_transforms(ConveyorDirection::Off), [ImageTransform::None]);
+    assert_eq!(conveyor_transforms(ConveyorDirection::Sticky), [ImageTransform::None]);
+  }
+}
@@ -0,0 +1,449 @@
+//! Emulates the attribute clash of the original hardware. Rather than drawing
+//! every sprite in its own colors, everything is composited into a single
+//! Spectrum [Screen], a 1-bit bitmap with one set of attributes per character
+//! cell, in the same order and with the same rules as the original game:
+//!
+//! 1. The cavern is drawn.
+//! 2. Any of the cells Willy is in that are empty background get white ink,
+//!    keeping the background's paper. Willy takes on the colors of any other
+//!    cell he's in.
+//! 3. Each guardian sets every cell it's in to its own attributes, and is then
+//!    drawn over the top of what's already there.
+//! 4. Items and the portal set their cells to their own attributes, and
+//!    replace what's already there.
+//! 5. Willy is drawn over the top of what's already there.
+//!
+//! Press C to switch between this and the normal rendering, and F12 to save
+//! the screen as a `.scr` file.
+
+use anyhow::Result;
+use bevy::{
+  ecs::system::SystemParam,
+  prelude::*,
+  render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+  sprite::Anchor,
+};
+
+use crate::{
+  actions::{Action, Actions},
+  actors::HorizontalMotion,
+  bitmap::Bitmap,
+  cavern::{CavernState, CurrentCavern},
+  color::Attributes,
+  despawn_all,
+  gamedata::{
+    cavern::{Cavern, CavernTileType},
+    GameData, GameDataResource,
+  },
+  guardian::{self, Guardian},
+  handle_errors,
+  item::{self, Item},
+  lives::Lives,
+  palette::Palette,
+  portal::Portal,
+  position::{Layer, Position},
+  render::{self, char_to_pixel, RenderMode, Status, SCREEN_WIDTH},
+  score::Score,
+  scr::{cells_covered, Screen},
+  text::CharsetResource,
+  willy::{self, Willy},
+  GameState,
+};
+
+/// Where [Action::ExportScreen] saves the screen.
+pub const SCREEN_EXPORT_PATH: &str = "minerwilly.scr";
+
+pub struct ClashPlugin;
+
+impl Plugin for ClashPlugin {
+  fn build(&self, app: &mut App) {
+    app.insert_resource(RenderMode::default());
+    app.add_systems(
+      Update,
+      (
+        check_keyboard,
+        show_clash_screen,
+        update_clash_screen.run_if(resource_equals(RenderMode::Clash)),
+      )
+        .chain(),
+    );
+    app.add_systems(Update, export_screen.pipe(handle_errors).run_if(in_state(GameState::Playing)));
+  }
+}
+
+/// Everything that's drawn on a single frame of the game.
+pub struct Scene<'a> {
+  pub cavern: &'a Cavern,
+  pub cavern_state: CavernState,
+  /// Willy's current sprite, and where it's drawn.
+  pub willy: (&'a Bitmap, (i32, i32)),
+  pub guardians: Vec<(&'a Bitmap, Attributes, (i32, i32))>,
+  /// The cell and current colors of each item that hasn't been collected.
+  pub items: Vec<((u8, u8), Attributes)>,
+  pub portal: Attributes,
+}
+
+impl<'a> Scene<'a> {
+  /// The given cavern as it is when Willy first enters it.
+  pub fn at_start(game_data: &'a GameData, number: usize) -> Self {
+    let cavern = &game_data.caverns[number];
+    let (_, position, motion) = willy::start_state(cavern);
+
+    Scene {
+      cavern,
+      cavern_state: CavernState::from(cavern),
+      willy: (&game_data.willy_sprites[motion.current_frame], sprite_pos(&position)),
+      guardians: cavern
+        .guardians
+        .iter()
+        .map(|g| {
+          let (position, motion) = guardian::start_position(g);
+          let sprite = guardian::sprite_index(number, motion.current_frame);
+          (&cavern.guardian_bitmaps[sprite], g.attributes, sprite_pos(&position))
+        })
+        .collect(),
+      items: cavern.items.iter().map(|i| (i.position, i.attributes)).collect(),
+      portal: cavern.portal.attributes,
+    }
+  }
+}
+
+/// Returns where a sprite at the given position is drawn. Like the sprites in
+/// the game, this stays at the left of the current cell, as the animation
+/// frames themselves move the sprite across it.
+pub fn sprite_pos(position: &Position) -> (i32, i32) {
+  let (x, y) = position.zx_pixel_pos();
+
+  (i32::from(x.div_euclid(8)) * 8, i32::from(y))
+}
+
+/// Draws the cavern (rows 0-15 of the screen) and everything in it.
+pub fn compose(screen: &mut Screen, scene: &Scene) {
+  let cavern = scene.cavern;
+  draw_cavern(screen, cavern, &scene.cavern_state);
+
+  let background = *cavern.tile_bitmaps[CavernTileType::Background as usize].color.as_ref().unwrap();
+  let (willy, willy_pos) = scene.willy;
+  for cell in cells_covered(willy, willy_pos) {
+    if *screen.get_attributes(cell) == background {
+      screen.set_attributes(cell, Attributes { ink: 7, ..background });
+    }
+  }
+
+  for (bitmap, color, pos) in scene.guardians.iter() {
+    for cell in cells_covered(bitmap, *pos) {
+      screen.set_attributes(cell, *color);
+    }
+    screen.or_bitmap(bitmap, *pos);
+  }
+
+  for ((x, y), color) in scene.items.iter() {
+    screen.set_attributes((*x as usize, *y as usize), *color);
+    screen.copy_bitmap(&cavern.item_bitmap, char_to_pixel((*x, *y)));
+  }
+
+  let portal = &cavern.portal;
+  let portal_pos = char_to_pixel(portal.position);
+  for cell in cells_covered(&portal.bitmap, portal_pos) {
+    screen.set_attributes(cell, scene.portal);
+  }
+  screen.copy_bitmap(&portal.bitmap, portal_pos);
+
+  screen.or_bitmap(willy, willy_pos);
+}
+
+fn draw_cavern(screen: &mut Screen, cavern: &Cavern, cavern_state: &CavernState) {
+  for y in 0..16 {
+    for x in 0..32 {
+      let tile_type = cavern_state.get_tile_type((x, y));
+      let mut tile = cavern.tile_bitmaps[tile_type as usize].clone();
+
+      // Crumbling floors sink a row for each level they've crumbled.
+      if tile_type == CavernTileType::CrumblingFloor {
+        for _ in cavern_state.get_crumble_level((x, y))..7 {
+          tile = tile.shift_down();
+        }
+      }
+
+      screen.set_attributes((x as usize, y as usize), *tile.color.as_ref().unwrap());
+      screen.copy_bitmap(&tile, char_to_pixel((x, y)));
+    }
+  }
+}
+
+/// The sprite that shows the clash rendered cavern over the top of the usual
+/// sprites.
+#[derive(Component)]
+struct ClashScreen;
+
+/// The size of the clash rendered part of the screen, which covers the cavern.
+const CLASH_SCREEN_HEIGHT: usize = 128;
+
+fn check_keyboard(actions: Actions, mut mode: ResMut<RenderMode>) {
+  if actions.just_released(Action::ToggleClash) {
+    *mode = match *mode {
+      RenderMode::Sprites => RenderMode::Clash,
+      RenderMode::Clash => RenderMode::Sprites,
+    };
+  }
+}
+
+fn show_clash_screen(
+  mut commands: Commands,
+  mode: Res<RenderMode>,
+  mut images: ResMut<Assets<Image>>,
+  query: Query<Entity, With<ClashScreen>>,
+) {
+  if mode.is_changed() {
+    despawn_all(&mut commands, query);
+
+    if *mode == RenderMode::Clash {
+      let image = Image::new_fill(
+        Extent3d {
+          width: SCREEN_WIDTH as u32,
+          height: CLASH_SCREEN_HEIGHT as u32,
+          depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8Unorm,
+      );
+
+      commands.spawn((
+        ClashScreen,
+        SpriteBundle {
+          sprite: Sprite {
+            anchor: Anchor::TopLeft,
+            ..Default::default()
+          },
+          texture: images.add(image),
+          transform: Position::at_char_pos(Layer::Screen, (0, 0)).into(),
+          ..Default::default()
+        },
+      ));
+    }
+  }
+}
+
+#[derive(SystemParam)]
+struct Actors<'w, 's> {
+  willy: Query<'w, 's, (&'static Position, &'static HorizontalMotion), With<Willy>>,
+  guardians: Query<'w, 's, (&'static Position, &'static HorizontalMotion, &'static Guardian)>,
+  items: Query<'w, 's, (&'static Position, &'static HorizontalMotion, &'static Item)>,
+  portal: Query<'w, 's, &'static Portal>,
+}
+
+/// The scene in the cavern being played, as it is right now.
+fn live_scene<'a>(
+  game_data: &'a GameData,
+  number: usize,
+  cavern_state: &CavernState,
+  actors: &Actors,
+) -> Option<Scene<'a>> {
+  let (willy_pos, willy_motion) = actors.willy.get_single().ok()?;
+  let portal = actors.portal.get_single().ok()?;
+  let cavern = &game_data.caverns[number];
+
+  Some(Scene {
+    cavern,
+    cavern_state: cavern_state.clone(),
+    willy: (&game_data.willy_sprites[willy_motion.current_frame], sprite_pos(willy_pos)),
+    guardians: actors
+      .guardians
+      .iter()
+      .map(|(pos, motion, guardian)| {
+        let sprite = guardian::sprite_index(number, motion.current_frame);
+        (&cavern.guardian_bitmaps[sprite], guardian.data.attributes, sprite_pos(pos))
+      })
+      .collect(),
+    items: actors
+      .items
+      .iter()
+      .filter(|(_, _, item)| !item.collected)
+      .filter_map(|(pos, motion, _)| {
+        let data = cavern.items.iter().find(|i| Some(i.position) == pos.char_pos())?;
+        Some((data.position, item::cycle_color(&data.attributes, motion.current_frame)))
+      })
+      .collect(),
+    portal: if portal.is_inverted() { cavern.portal.attributes.inverse() } else { cavern.portal.attributes },
+  })
+}
+
+fn update_clash_screen(
+  game_data: Res<GameDataResource>,
+  cavern: Res<CurrentCavern>,
+  cavern_state: Res<CavernState>,
+  actors: Actors,
+  palette: Res<Palette>,
+  mut images: ResMut<Assets<Image>>,
+  query: Query<&Handle<Image>, With<ClashScreen>>,
+) {
+  let (Some(scene), Ok(handle)) = (live_scene(&game_data, cavern.number, &cavern_state, &actors), query.get_single())
+  else {
+    return;
+  };
+
+  let mut screen = Screen::blank();
+  compose(&mut screen, &scene);
+
+  if let Some(image) = images.get_mut(handle) {
+    let mut rgba = screen.render_to_rgba(&palette);
+    rgba.truncate(SCREEN_WIDTH * CLASH_SCREEN_HEIGHT * 4);
+    image.data = rgba;
+  }
+}
+
+/// Saves the whole screen as the Spectrum would show it, with attribute
+/// clash, as a `.scr` file.
+#[allow(clippy::too_many_arguments)]
+fn export_screen(
+  actions: Actions,
+  game_data: Res<GameDataResource>,
+  charset: Res<CharsetResource>,
+  cavern: Res<CurrentCavern>,
+  cavern_state: Res<CavernState>,
+  actors: Actors,
+  score: Res<Score>,
+  lives: Res<Lives>,
+) -> Result<()> {
+  if !actions.just_pressed(Action::ExportScreen) {
+    return Ok(());
+  }
+  let Some(scene) = live_scene(&game_data, cavern.number, &cavern_state, &actors) else {
+    return Ok(());
+  };
+
+  let mut screen = Screen::blank();
+  compose(&mut screen, &scene);
+  let status = Status { score: score.score, high_score: score.high_score, lives: lives.lives_remaining };
+  render::draw_status_area(&mut screen, &game_data, &charset, &scene.cavern.name, &status);
+  screen.save(SCREEN_EXPORT_PATH)?;
+  info!("Saved the screen to {}", SCREEN_EXPORT_PATH);
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gamedata::GAME_DATA_PATH;
+
+  #[test]
+  fn willy_takes_on_the_colors_of_his_cells() -> Result<()> {
+    let game_data = GameData::load(GAME_DATA_PATH)?;
+    let cavern = &game_data.caverns[0];
+    let background = *cavern.tile_bitmaps[0].color.as_ref().unwrap();
+    let floor = *cavern.tile_bitmaps[1].color.as_ref().unwrap();
+
+    // Put Willy so that his feet overlap the floor below him.
+    let mut scene = Scene::at_start(&game_data, 0);
+    let (x, y) = cavern.willy_start.position;
+    scene.willy.1 = (x as i32 * 8, y as i32 * 8 + 4);
+
+    let mut screen = Screen::blank();
+    compose(&mut screen, &scene);
+
+    let (x, y) = (x as usize, y as usize);
+    assert_eq!(*screen.get_attributes((x, y)), Attributes { ink: 7, ..background });
+    assert_eq!(*screen.get_attributes((x, y + 2)), floor);
+
+    Ok(())
+  }
+
+  /// The first cavern with nothing in it but the given tiles, and Willy drawn
+  /// at the given position.
+  fn empty_scene<'a>(game_data: &'a GameData, tiles: &[((u8, u8), CavernTileType)], willy: (i32, i32)) -> Scene<'a> {
+    Scene {
+      cavern_state: CavernState::with_tiles(tiles),
+      willy: (&game_data.willy_sprites[0], willy),
+      guardians: vec![],
+      items: vec![],
+      ..Scene::at_start(game_data, 0)
+    }
+  }
+
+  /// Returns true if the pixels in the given cell are exactly those of the
+  /// bitmap drawn at the given position.
+  fn cell_shows(screen: &Screen, (x, y): (usize, usize), bitmap: &Bitmap, (bx, by): (i32, i32)) -> bool {
+    (0..8).all(|row| {
+      (0..8).all(|column| {
+        let (sx, sy) = (x * 8 + column, y * 8 + row);
+        let (px, py) = (sx as i32 - bx, sy as i32 - by);
+        let inside = (0..bitmap.width() as i32).contains(&px) && (0..bitmap.height() as i32).contains(&py);
+        screen.bitmap.get_pixel((sx, sy)) == (inside && bitmap.get_pixel((px as usize, py as usize)))
+      })
+    })
+  }
+
+  #[test]
+  fn willy_clashes_with_every_cell_he_covers() -> Result<()> {
+    let game_data = GameData::load(GAME_DATA_PATH)?;
+    let cavern = &game_data.caverns[0];
+    let color = |tile: CavernTileType| *cavern.tile_bitmaps[tile as usize].color.as_ref().unwrap();
+
+    // Willy is half a cell down, so his feet are in a floor and a wall.
+    let tiles = [((10, 10), CavernTileType::Floor), ((11, 10), CavernTileType::Wall)];
+    let scene = empty_scene(&game_data, &tiles, (80, 68));
+    let mut screen = Screen::blank();
+    compose(&mut screen, &scene);
+
+    for cell in [(10, 8), (11, 8), (10, 9), (11, 9)] {
+      assert_eq!(*screen.get_attributes(cell), Attributes { ink: 7, ..color(CavernTileType::Background) });
+    }
+    assert_eq!(*screen.get_attributes((10, 10)), color(CavernTileType::Floor));
+    assert_eq!(*screen.get_attributes((11, 10)), color(CavernTileType::Wall));
+    assert_eq!(*screen.get_attributes((12, 9)), color(CavernTileType::Background));
+
+    Ok(())
+  }
+
+  #[test]
+  fn later_guardians_take_over_shared_cells() -> Result<()> {
+    let game_data = GameData::load(GAME_DATA_PATH)?;
+    let bitmap = &game_data.caverns[0].guardian_bitmaps[0];
+    let first = Attributes { ink: 2, ..Default::default() };
+    let second = Attributes { ink: 5, ..Default::default() };
+
+    // The second guardian is a cell to the right of the first, so they share
+    // a column.
+    let mut scene = empty_scene(&game_data, &[], (0, 0));
+    scene.guardians = vec![(bitmap, first, (80, 64)), (bitmap, second, (88, 64))];
+    let mut screen = Screen::blank();
+    compose(&mut screen, &scene);
+
+    assert_eq!(*screen.get_attributes((10, 8)), first);
+    assert_eq!(*screen.get_attributes((11, 8)), second);
+    assert_eq!(*screen.get_attributes((12, 9)), second);
+
+    // Both are drawn in full, over the top of each other.
+    let mut both = Screen::blank();
+    both.or_bitmap(bitmap, (80, 64));
+    both.or_bitmap(bitmap, (88, 64));
+    assert_eq!(screen.bitmap.data()[8 * 8 * 32..10 * 8 * 32], both.bitmap.data()[8 * 8 * 32..10 * 8 * 32]);
+
+    Ok(())
+  }
+
+  #[test]
+  fn items_replace_whatever_is_in_their_cell() -> Result<()> {
+    let game_data = GameData::load(GAME_DATA_PATH)?;
+    let cavern = &game_data.caverns[0];
+    let bitmap = &cavern.guardian_bitmaps[0];
+    let guardian = Attributes { ink: 2, ..Default::default() };
+    let item = Attributes { ink: 6, ..Default::default() };
+
+    let mut scene = empty_scene(&game_data, &[], (0, 0));
+    scene.guardians = vec![(bitmap, guardian, (80, 64))];
+    scene.items = vec![((10, 8), item)];
+    let mut screen = Screen::blank();
+    compose(&mut screen, &scene);
+
+    assert_eq!(*screen.get_attributes((10, 8)), item);
+    assert!(cell_shows(&screen, (10, 8), &cavern.item_bitmap, (80, 64)));
+    // The rest of the guardian is left alone.
+    assert_eq!(*screen.get_attributes((11, 8)), guardian);
+    assert!(cell_shows(&screen, (11, 8), bitmap, (80, 64)));
+
+    Ok(())
+  }
+}
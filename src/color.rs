@@ -1,9 +1,11 @@
 use anyhow::Result;
 use bevy::prelude::Color;
 
+use crate::palette::Palette;
+
 /// ZX Spectrum color attributes. Consists of an ink value 0-7, a paper
-/// value 0-7, and a boolean bright flag.
-#[derive(Debug, Eq, PartialEq, Default, Copy, Clone)]
+/// value 0-7, and boolean bright and flash flags.
+#[derive(Debug, Eq, PartialEq, Hash, Default, Copy, Clone)]
 pub struct Attributes {
   // The ink color index 0..7
   pub ink: u8,
@@ -11,6 +13,9 @@ pub struct Attributes {
   pub paper: u8,
   // The bright flag
   pub bright: bool,
+  // The flash flag, which makes the hardware swap ink and paper every 16
+  // frames.
+  pub flash: bool,
   // The transparent paper flag. This indicates to the rendering
   // system that the paper should be rendered as a transparent
   // pixel.
@@ -77,6 +82,7 @@ impl Attributes {
       ink: self.paper,
       paper: self.ink,
       bright: self.bright,
+      flash: self.flash,
       transparent_background: self.transparent_background
     }
   }
@@ -86,40 +92,24 @@ impl Attributes {
       ink: ink.into(),
       paper: ColorName::Black.into(),
       bright,
+      flash: false,
       transparent_background: true,
     }
   }
 
-  /// Converts a color into an rgba color value
-  fn to_rgba(self, value: &u8) -> Vec<u8> {
-    let code = if self.bright { 0xff } else { 0xee };
-
-    let mut red = 0;
-    let mut green = 0;
-    let mut blue = 0;
-    let alpha = 255;
-
-    if (value & 0b010) != 0 {
-      red = code;
-    }
-    if (value & 0b100) != 0 {
-      green = code;
-    }
-    if (value & 1) != 0 {
-      blue = code;
-    }
-    // TODO: there are a finite number of these - we shouldn't create them over and over
-    vec![red, green, blue, alpha]
+  /// Converts a color into an rgba color value using the given palette.
+  fn to_rgba(self, value: &u8, palette: &Palette) -> [u8; 4] {
+    palette.rgba(*value, self.bright)
   }
 
   /// Returns the rgba representation of the ink of this color.
-  pub fn ink_rgba(&self) -> Vec<u8> {
-    self.to_rgba(&self.ink)
+  pub fn ink_rgba(&self, palette: &Palette) -> [u8; 4] {
+    self.to_rgba(&self.ink, palette)
   }
 
   /// Returns the rgba representation of the paper of this color.
-  pub fn paper_rgba(&self) -> Vec<u8> {
-    let mut rgba = self.to_rgba(&self.paper);
+  pub fn paper_rgba(&self, palette: &Palette) -> [u8; 4] {
+    let mut rgba = self.to_rgba(&self.paper, palette);
     if self.transparent_background {
       rgba[3] = 0;
     }
@@ -127,12 +117,12 @@ impl Attributes {
     rgba
   }
 
-  pub fn ink_color(&self) -> Color {
-    convert_color(&self.ink_rgba())
+  pub fn ink_color(&self, palette: &Palette) -> Color {
+    convert_color(&self.ink_rgba(palette))
   }
 
-  pub fn paper_color(&self) -> Color {
-    convert_color(&self.paper_rgba())
+  pub fn paper_color(&self, palette: &Palette) -> Color {
+    convert_color(&self.paper_rgba(palette))
   }
 
   pub fn ink_color_name(&self) -> ColorName {
@@ -154,11 +144,13 @@ impl From<u8> for Attributes {
     let ink: u8 = b & 0b111;
     let paper: u8 = (b >> 3) & 0b111;
     let bright = ((b >> 6) & 1) == 1;
+    let flash = ((b >> 7) & 1) == 1;
 
     Attributes {
       ink,
       paper,
       bright,
+      flash,
       ..Default::default()
     }
   }
@@ -183,13 +175,17 @@ impl TryFrom<&String> for Attributes {
 
 impl From<&Attributes> for u8 {
   fn from(color: &Attributes) -> u8 {
-    color.ink | (color.paper << 3) | if color.bright { 0b1000000 } else { 0 }
+    color.ink
+      | (color.paper << 3)
+      | if color.bright { 0b1000000 } else { 0 }
+      | if color.flash { 0b10000000 } else { 0 }
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::Attributes;
+  use crate::palette::Palette;
 
   #[test]
   fn can_convert_to_u8() {
@@ -220,6 +216,29 @@ mod tests {
       }),
       0b1111111
     );
+    assert_eq!(
+      u8::from(&Attributes {
+        paper: 1,
+        ink: 6,
+        flash: true,
+        ..Default::default()
+      }),
+      0b10001110
+    );
+  }
+
+  #[test]
+  fn can_convert_from_u8() {
+    assert_eq!(
+      Attributes::from(0b11001110),
+      Attributes {
+        paper: 1,
+        ink: 6,
+        bright: true,
+        flash: true,
+        ..Default::default()
+      }
+    );
   }
 
   #[test]
@@ -230,7 +249,8 @@ mod tests {
       bright: true,
       ..Default::default()
     };
-    assert_eq!(vec![0xff, 0x00, 0x00, 0xff], color.ink_rgba());
-    assert_eq!(vec![0x00, 0xff, 0xff, 0xff], color.paper_rgba());
+    let palette = Palette::default();
+    assert_eq!([0xff, 0x00, 0x00, 0xff], color.ink_rgba(&palette));
+    assert_eq!([0x00, 0xff, 0xff, 0xff], color.paper_rgba(&palette));
   }
 }
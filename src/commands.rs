@@ -0,0 +1,264 @@
+//! Subcommands that can be run from the command line instead of starting the
+//! game, e.g. `minerwilly analyse 3`.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::{
+  bot::{self, Replay},
+  cavern::CavernState,
+  gamedata::{
+    cavern::{Cavern, CavernTileType},
+    GameData, GAME_DATA_PATH,
+  },
+  palette::Palette,
+  reachability::{self, Reachability},
+  render::{self, Frame, RenderMode},
+  scr::Screen,
+  simulation::{self, Outcome},
+  text::{Charset, CHARSET_PATH},
+};
+
+pub fn run(command: &str, args: &[String]) -> Result<()> {
+  match command {
+    "analyse" => analyse(args),
+    "bot" => bot(args),
+    "replay" => replay(args),
+    "render" => render(args),
+    "convert-scr" => convert_scr(args),
+    _ => anyhow::bail!(
+      "Unknown command `{}`. Expected one of: analyse, bot, replay, render, convert-scr",
+      command
+    ),
+  }
+}
+
+/// Returns the cavern numbers selected by the arguments. With no arguments,
+/// every cavern is selected.
+fn select_caverns(game_data: &GameData, args: &[String]) -> Result<Vec<usize>> {
+  if args.is_empty() {
+    return Ok((0..game_data.caverns.len()).collect());
+  }
+
+  args
+    .iter()
+    .map(|arg| {
+      let number: usize = arg.parse()?;
+      anyhow::ensure!(
+        number < game_data.caverns.len(),
+        "No such cavern: {}",
+        number
+      );
+      Ok(number)
+    })
+    .collect()
+}
+
+/// Splits an optional leading `--out <dir>` off the arguments.
+fn out_dir(args: &[String]) -> (Option<&String>, &[String]) {
+  match args {
+    [flag, dir, rest @ ..] if flag == "--out" => (Some(dir), rest),
+    _ => (None, args),
+  }
+}
+
+/// Prints whether each selected cavern can be completed, ignoring guardians,
+/// along with a route through it (or a map of where Willy can get to, if it
+/// can't be completed).
+fn analyse(args: &[String]) -> Result<()> {
+  let game_data = GameData::load(GAME_DATA_PATH)?;
+
+  for number in select_caverns(&game_data, args)? {
+    let cavern = &game_data.caverns[number];
+    let reachability = reachability::analyse(cavern);
+
+    println!(
+      "{:>2} {}: {}",
+      number,
+      cavern.name.trim(),
+      if reachability.is_solvable() {
+        "solvable"
+      } else {
+        "NOT solvable"
+      }
+    );
+
+    for item in reachability.items.iter().filter(|i| !i.is_reachable()) {
+      println!("   item at {:?} can't be reached", item.position);
+    }
+    if !reachability.portal.is_reachable() {
+      println!(
+        "   portal at {:?} can't be reached",
+        reachability.portal.position
+      );
+    }
+
+    if !reachability.is_solvable() {
+      print_map(cavern, &reachability);
+    } else {
+      match reachability::find_route(cavern, bot::MAX_PLANNING_NODES) {
+        Some(route) => {
+          println!(
+            "   route ({} ticks): {}",
+            route.len(),
+            simulation::describe_route(&route)
+          )
+        }
+        None => println!("   couldn't find a route that collects every item"),
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Has the bot play through each selected cavern, and checks that the replay
+/// it produces completes the cavern. With `--out <dir>`, replays are saved as
+/// `<dir>/cavern-<number>.replay`. Fails if any cavern can't be completed.
+fn bot(args: &[String]) -> Result<()> {
+  let (out_dir, args) = out_dir(args);
+  let game_data = GameData::load(GAME_DATA_PATH)?;
+  let mut failed = vec![];
+
+  for number in select_caverns(&game_data, args)? {
+    let cavern = &game_data.caverns[number];
+
+    let Some(replay) = bot::play(number, cavern) else {
+      println!("{:>2} {}: no route found", number, cavern.name.trim());
+      failed.push(number);
+      continue;
+    };
+
+    let outcome = simulation::play(cavern, &replay.inputs);
+    println!("{:>2} {}: {:?}", number, cavern.name.trim(), outcome);
+    if !matches!(outcome, Outcome::Completed { .. }) {
+      failed.push(number);
+    }
+
+    match out_dir {
+      Some(dir) => {
+        std::fs::create_dir_all(dir)?;
+        replay.save(format!("{}/cavern-{:02}.replay", dir, number))?;
+      }
+      None => print!("{}", replay),
+    }
+  }
+
+  anyhow::ensure!(failed.is_empty(), "Couldn't complete caverns {:?}", failed);
+  Ok(())
+}
+
+/// Plays back each of the given replay files without a window, and checks that
+/// they complete their cavern.
+fn replay(args: &[String]) -> Result<()> {
+  anyhow::ensure!(!args.is_empty(), "Expected one or more replay files");
+  let game_data = GameData::load(GAME_DATA_PATH)?;
+  let mut failed = vec![];
+
+  for path in args {
+    let replay = Replay::load(path)?;
+    let cavern = game_data
+      .caverns
+      .get(replay.cavern)
+      .ok_or_else(|| anyhow::anyhow!("{}: no such cavern {}", path, replay.cavern))?;
+
+    let outcome = simulation::play(cavern, &replay.inputs);
+    println!("{}: {:?}", path, outcome);
+    if !matches!(outcome, Outcome::Completed { .. }) {
+      failed.push(path.as_str());
+    }
+  }
+
+  anyhow::ensure!(
+    failed.is_empty(),
+    "Replays didn't complete their caverns: {:?}",
+    failed
+  );
+  Ok(())
+}
+
+/// Saves a screenshot of each selected cavern as it looks when Willy enters it,
+/// as `<dir>/cavern-<number>.png`. The directory defaults to the current one.
+/// With `--scr`, screenshots are saved as Spectrum `.scr` files instead, and
+/// with `--clash` they are drawn with attribute clash like the original game.
+/// `--palette <name>` draws them with one of the preset palettes.
+fn render(args: &[String]) -> Result<()> {
+  let (out_dir, mut args) = out_dir(args);
+  let dir = out_dir.map_or(".", String::as_str);
+  let (mut as_scr, mut mode, mut palette) = (false, RenderMode::Sprites, Palette::default());
+  while let [flag, rest @ ..] = args {
+    match flag.as_str() {
+      "--scr" => as_scr = true,
+      "--clash" => mode = RenderMode::Clash,
+      "--palette" => {
+        let [name, rest @ ..] = rest else {
+          anyhow::bail!("Expected a palette name after --palette");
+        };
+        palette = Palette::named(name).ok_or_else(|| {
+          let names: Vec<_> = Palette::presets().iter().map(|p| p.name).collect();
+          anyhow::anyhow!("Unknown palette `{}`. Expected one of: {}", name, names.join(", "))
+        })?;
+        args = rest;
+        continue;
+      }
+      _ => break,
+    }
+    args = rest;
+  }
+  let game_data = GameData::load(GAME_DATA_PATH)?;
+  let charset = Charset::load(CHARSET_PATH)?;
+
+  std::fs::create_dir_all(dir)?;
+  for number in select_caverns(&game_data, args)? {
+    let frame = render::render_cavern(&game_data, &charset, number, mode, &palette);
+    let path = format!("{}/cavern-{:02}.{}", dir, number, if as_scr { "scr" } else { "png" });
+    if as_scr {
+      Screen::from(&frame).save(&path)?;
+    } else {
+      frame.save_png(&path)?;
+    }
+    println!("{}", path);
+  }
+
+  Ok(())
+}
+
+/// Converts each of the given `.scr` files to a PNG alongside it.
+fn convert_scr(args: &[String]) -> Result<()> {
+  anyhow::ensure!(!args.is_empty(), "Expected one or more .scr files");
+
+  for path in args {
+    let out = Path::new(path).with_extension("png");
+    Frame::from(&Screen::load(path)?).save_png(&out)?;
+    println!("{}", out.display());
+  }
+
+  Ok(())
+}
+
+/// Prints the cavern with the cells Willy can reach marked with `.`, items
+/// with `*` and the portal with `P`. Walls are shown as `#`.
+fn print_map(cavern: &Cavern, reachability: &Reachability) {
+  let state = CavernState::from(cavern);
+  let (portal_x, portal_y) = cavern.portal.position;
+
+  for y in 0..16 {
+    let row: String = (0..32)
+      .map(|x| {
+        if cavern.items.iter().any(|i| i.position == (x, y)) {
+          '*'
+        } else if (portal_x..=portal_x + 1).contains(&x) && (portal_y..=portal_y + 1).contains(&y) {
+          'P'
+        } else if reachability.can_reach((x, y)) {
+          '.'
+        } else if state.get_tile_type((x, y)) == CavernTileType::Wall {
+          '#'
+        } else {
+          ' '
+        }
+      })
+      .collect();
+    println!("   |{}|", row);
+  }
+}
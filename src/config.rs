@@ -0,0 +1,116 @@
+//! Settings that are kept between runs of the game. These are stored as
+//! `name = value` lines in [CONFIG_PATH], in the current directory. Blank
+//! lines and lines starting with `#` are ignored.
+
+use std::{fs, io::ErrorKind, path::Path};
+
+use anyhow::Result;
+use bevy::prelude::*;
+
+use crate::handle_errors;
+
+/// The file that settings are loaded from and saved to.
+pub const CONFIG_PATH: &str = "minerwilly.cfg";
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+  fn build(&self, app: &mut App) {
+    let config = Config::load(CONFIG_PATH).unwrap_or_else(|e| {
+      eprintln!("Error: couldn't load {}: {}", CONFIG_PATH, e);
+      Config::default()
+    });
+
+    app.insert_resource(config);
+    app.add_systems(Last, save_config.pipe(handle_errors));
+  }
+}
+
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct Config {
+  entries: Vec<(String, String)>,
+}
+
+impl Config {
+  /// Loads settings from the given file. A missing file has no settings.
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    match fs::read_to_string(path) {
+      Ok(s) => Config::try_from(s.as_str()),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    fs::write(path, String::from(self))?;
+    Ok(())
+  }
+
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self.entries.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+  }
+
+  /// Changes a setting, adding it to the end if it's new.
+  pub fn set(&mut self, name: &str, value: String) {
+    match self.entries.iter_mut().find(|(n, _)| n == name) {
+      Some((_, v)) => *v = value,
+      None => self.entries.push((name.to_owned(), value)),
+    }
+  }
+}
+
+impl TryFrom<&str> for Config {
+  type Error = anyhow::Error;
+
+  fn try_from(s: &str) -> Result<Self> {
+    let mut config = Config::default();
+
+    for (number, line) in s.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let Some((name, value)) = line.split_once('=') else {
+        anyhow::bail!("Line {}: expected `name = value`", number + 1);
+      };
+      config.set(name.trim(), value.trim().to_owned());
+    }
+
+    Ok(config)
+  }
+}
+
+impl From<&Config> for String {
+  fn from(config: &Config) -> String {
+    config.entries.iter().map(|(name, value)| format!("{} = {}\n", name, value)).collect()
+  }
+}
+
+fn save_config(config: Res<Config>) -> Result<()> {
+  if config.is_changed() && !config.is_added() {
+    config.save(CONFIG_PATH)?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_and_writes_settings() -> Result<()> {
+    let mut config = Config::try_from("# Keys\nkey.left = O Left\n\nkey.jump=Space\n")?;
+    assert_eq!(config.get("key.left"), Some("O Left"));
+    assert_eq!(config.get("key.jump"), Some("Space"));
+    assert_eq!(config.get("key.right"), None);
+
+    config.set("key.right", "P".to_owned());
+    assert_eq!(String::from(&config), "key.left = O Left\nkey.jump = Space\nkey.right = P\n");
+
+    assert!(Config::try_from("key.left").is_err());
+
+    Ok(())
+  }
+}
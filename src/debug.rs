@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 
 use crate::{
+  actions::{Action, Actions},
   color::ColorName,
   position::{Layer, Position, vec2},
   text::{Text, TextAttributes},
@@ -8,8 +9,7 @@ use crate::{
 
 /// Provides the debug plugin which inserts helpful stuff like a HUD to make it easier to
 /// debug the state of the running app. The debug plugin is pluggable itself - plugins
-/// contribute text to it by obtaining the DebugText resource and mutating it.s
-
+/// contribute text to it by obtaining the DebugText resource and mutating it.
 pub struct DebugPlugin;
 
 #[derive(Resource)]
@@ -139,9 +139,9 @@ fn draw_grid(mut gizmos: Gizmos, debug_state: Res<DebugState>) {
   }
 }
 
-fn check_keyboard(mut debug_state: ResMut<DebugState>, keys: Res<Input<KeyCode>>,
+fn check_keyboard(mut debug_state: ResMut<DebugState>, actions: Actions,
     mut event_writer: EventWriter<DebugStateToggled>) {
-  if keys.just_pressed(KeyCode::D) {
+  if actions.just_pressed(Action::ToggleDebug) {
     let on = debug_state.toggle();
     event_writer.send(DebugStateToggled(on));
   }
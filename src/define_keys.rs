@@ -0,0 +1,223 @@
+//! The define keys screen, reached from the title screen. It asks for a key
+//! (or gamepad button) for each of the actions used while playing, one at a
+//! time, then saves them to the config file and goes back to the title
+//! screen. Keys that are already used for something else while playing are
+//! refused, and quitting goes back without changing anything.
+
+use bevy::prelude::*;
+
+use crate::{
+  actions::{Action, Actions, Binding, KeyBindings, PadBindings, PadInput},
+  color::ColorName,
+  config::Config,
+  despawn_all,
+  position::Layer,
+  text::{Alignment, Text, TextAttributes},
+  title::backdrop,
+  GameState,
+};
+
+pub struct DefineKeysPlugin;
+
+impl Plugin for DefineKeysPlugin {
+  fn build(&self, app: &mut App) {
+    app.add_systems(OnEnter(GameState::DefineKeys), show_define_keys);
+    app.add_systems(OnExit(GameState::DefineKeys), hide_define_keys);
+    app.add_systems(Update, check_keyboard.run_if(in_state(GameState::DefineKeys)));
+  }
+}
+
+/// Everything that makes up the define keys screen.
+#[derive(Component)]
+struct DefineKeysScreen;
+
+/// The line showing the key chosen for one of the actions.
+#[derive(Component)]
+struct KeyLine(usize);
+
+/// The line at the bottom, which asks for keys or says why one was refused.
+#[derive(Component)]
+struct PromptLine;
+
+/// A key or gamepad button chosen for an action. Choosing one replaces the
+/// action's bindings of the same kind, and leaves the other kind alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Choice {
+  Key(KeyCode),
+  Pad(PadInput),
+}
+
+impl Choice {
+  fn name(&self) -> String {
+    match self {
+      Choice::Key(key) => key.name(),
+      Choice::Pad(input) => input.name(),
+    }
+  }
+
+  /// The action this key or button is already used for while playing, apart
+  /// from the ones being chosen. Keys only used on the title screen are free,
+  /// as they do nothing while playing.
+  fn used_for(&self, key_bindings: &KeyBindings, pad_bindings: &PadBindings) -> Option<Action> {
+    Action::ALL
+      .into_iter()
+      .filter(|action| !Action::PLAYING.contains(action) && !Action::TITLE.contains(action))
+      .find(|action| match self {
+        Choice::Key(key) => key_bindings.get(*action).contains(key),
+        Choice::Pad(input) => pad_bindings.get(*action).contains(input),
+      })
+  }
+}
+
+/// The keys chosen so far, one for each of [Action::PLAYING] in order.
+#[derive(Resource, Default)]
+struct ChosenKeys(Vec<Choice>);
+
+/// The row that the first action's line is shown on.
+const FIRST_ROW: u8 = 7;
+
+const PROMPT: &str = "Press a key or button for each";
+
+fn show_define_keys(mut commands: Commands, key_bindings: Res<KeyBindings>) {
+  commands.insert_resource(ChosenKeys::default());
+  commands.spawn((DefineKeysScreen, backdrop()));
+
+  let yellow = TextAttributes::new_bright(ColorName::Yellow, ColorName::Black);
+  let white = TextAttributes::new(ColorName::White, ColorName::Black);
+
+  commands.spawn((
+    DefineKeysScreen,
+    Text::new_with_layer("DEFINE KEYS", (0, 3), &yellow, Layer::Overlay).wrapped((32, 1), Alignment::Centre),
+  ));
+  commands.spawn((
+    DefineKeysScreen,
+    PromptLine,
+    Text::new_with_layer(PROMPT, (0, 20), &white, Layer::Overlay).wrapped((32, 1), Alignment::Centre),
+  ));
+  commands.spawn((
+    DefineKeysScreen,
+    Text::new_with_layer(
+      &format!("{} = Cancel", key_bindings.describe(Action::Quit)),
+      (0, 21),
+      &white,
+      Layer::Overlay,
+    )
+    .wrapped((32, 1), Alignment::Centre),
+  ));
+
+  for (i, action) in Action::PLAYING.iter().enumerate() {
+    let (value, attributes) = key_line(*action, None, i == 0);
+    commands.spawn((
+      DefineKeysScreen,
+      KeyLine(i),
+      Text::new_with_layer(&value, (8, FIRST_ROW + 2 * i as u8), &attributes, Layer::Overlay),
+    ));
+  }
+}
+
+/// The text and attributes of the line for an action. The line flashes while
+/// waiting for its key.
+fn key_line(action: Action, choice: Option<Choice>, waiting: bool) -> (String, TextAttributes) {
+  let name = action.name().to_uppercase();
+
+  match choice {
+    Some(choice) => (format!("{:<8}{}", name, choice.name()), TextAttributes::new(ColorName::Cyan, ColorName::Black)),
+    None if waiting => (
+      format!("{:<8}?", name),
+      TextAttributes::new_bright(ColorName::White, ColorName::Red).flashing(),
+    ),
+    None => (format!("{:<8}?", name), TextAttributes::new(ColorName::White, ColorName::Black)),
+  }
+}
+
+fn hide_define_keys(mut commands: Commands, query: Query<Entity, With<DefineKeysScreen>>) {
+  commands.remove_resource::<ChosenKeys>();
+  despawn_all(&mut commands, query);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn check_keyboard(
+  actions: Actions,
+  keys: Res<Input<KeyCode>>,
+  pad: Res<Input<PadInput>>,
+  mut chosen: ResMut<ChosenKeys>,
+  mut key_bindings: ResMut<KeyBindings>,
+  mut pad_bindings: ResMut<PadBindings>,
+  mut config: ResMut<Config>,
+  mut next_state: ResMut<NextState<GameState>>,
+  mut query: Query<(&KeyLine, &mut Text), Without<PromptLine>>,
+  mut prompt: Query<&mut Text, With<PromptLine>>,
+) {
+  if actions.just_pressed(Action::Quit) {
+    next_state.set(GameState::Title);
+    return;
+  }
+
+  let pressed = keys.get_just_pressed().map(|key| Choice::Key(*key));
+  let Some(choice) = pressed.chain(pad.get_just_pressed().map(|input| Choice::Pad(*input))).next() else {
+    return;
+  };
+
+  // Each action needs a different key, which isn't needed for anything else.
+  // A refused key flashes up with what it's used for.
+  let used_for = match chosen.0.iter().position(|c| *c == choice) {
+    Some(i) => Some(Action::PLAYING[i]),
+    None => choice.used_for(&key_bindings, &pad_bindings),
+  };
+  for mut text in prompt.iter_mut() {
+    (text.value, text.attributes) = match used_for {
+      Some(action) => (
+        format!("{} = {}", choice.name(), action.name()),
+        TextAttributes::new_bright(ColorName::White, ColorName::Red).flashing(),
+      ),
+      None => (PROMPT.to_owned(), TextAttributes::new(ColorName::White, ColorName::Black)),
+    };
+  }
+  if used_for.is_some() {
+    return;
+  }
+  chosen.0.push(choice);
+
+  for (line, mut text) in query.iter_mut() {
+    let (value, attributes) =
+      key_line(Action::PLAYING[line.0], chosen.0.get(line.0).copied(), line.0 == chosen.0.len());
+    if text.value != value || text.attributes != attributes {
+      text.value = value;
+      text.attributes = attributes;
+    }
+  }
+
+  if chosen.0.len() == Action::PLAYING.len() {
+    for (action, choice) in Action::PLAYING.iter().zip(chosen.0.iter()) {
+      match choice {
+        Choice::Key(key) => key_bindings.set(*action, vec![*key]),
+        Choice::Pad(input) => pad_bindings.set(*action, vec![*input]),
+      }
+    }
+    key_bindings.save_to(&mut config);
+    pad_bindings.save_to(&mut config);
+    next_state.set(GameState::Title);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keys_used_while_playing_are_refused() {
+    let key_bindings = KeyBindings::default();
+    let pad_bindings = PadBindings::default();
+    let used_for = |choice: Choice| choice.used_for(&key_bindings, &pad_bindings);
+
+    assert_eq!(used_for(Choice::Key(KeyCode::K)), Some(Action::CyclePalette));
+    assert_eq!(used_for(Choice::Key(KeyCode::X)), Some(Action::DebugUnlockPortal));
+    assert_eq!(used_for(Choice::Key(KeyCode::Escape)), Some(Action::Quit));
+    assert_eq!(used_for(Choice::Pad(PadInput::Button(GamepadButtonType::North))), Some(Action::Quit));
+
+    // Keys for the actions being chosen, or only used on the title screen.
+    assert_eq!(used_for(Choice::Key(KeyCode::Space)), None);
+    assert_eq!(used_for(Choice::Key(KeyCode::Return)), None);
+    assert_eq!(used_for(Choice::Pad(PadInput::Button(GamepadButtonType::Start))), None);
+  }
+}
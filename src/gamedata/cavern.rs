@@ -26,6 +26,8 @@ pub struct Cavern {
   // pub special_behaviors: HashSet<SpecialBehavior>,
   pub items: Vec<Item>,
   pub item_bitmap: Bitmap,
+  /// The value the game clock starts at, which goes down by 4 every tick.
+  pub clock: u8,
 }
 
 /// There are eight types of cavern tiles.
@@ -112,9 +114,9 @@ impl TryFrom<&[u8]> for Cavern {
       pos = end;
     }
 
-    let willy_start = WillyStart::try_from(&bytes[616..=622])?;
-    let conveyor = Conveyor::try_from(&bytes[623..=626])?;
-    let border_color = Attributes::try_from(bytes[627])?;
+    let willy_start = WillyStart::from(&bytes[616..=622]);
+    let conveyor = Conveyor::from(&bytes[623..=626]);
+    let border_color = Attributes::from(bytes[627]);
     let portal: Portal = Portal::try_from(&bytes[655..692])?;
 
     let mut guardians = Vec::with_capacity(4);
@@ -143,6 +145,7 @@ impl TryFrom<&[u8]> for Cavern {
     }
 
     let item_bitmap = Bitmap::create(8, 8, &bytes[692..=699]);
+    let clock = bytes[701];
 
     Ok(Cavern {
       layout,
@@ -157,6 +160,7 @@ impl TryFrom<&[u8]> for Cavern {
       // special_behaviors: HashSet::new(),
       items,
       item_bitmap,
+      clock,
     })
   }
 }
@@ -185,7 +189,7 @@ impl TryFrom<&[u8]> for Layout {
     let mut cells: Vec<Attributes> = Vec::with_capacity(512);
 
     for byte in bytes {
-      cells.push(Attributes::try_from(*byte)?)
+      cells.push(Attributes::from(*byte))
     }
 
     Ok(Layout { cells })
@@ -211,7 +215,7 @@ impl TryFrom<&[u8]> for Guardian {
     let speed = if guardian_data[0] & 0b10000000 == 0 {
       GuardianSpeed::Normal
     } else {
-      GuardianSpeed::Fast
+      GuardianSpeed::Slow
     };
 
     let mut attributes: Attributes = (guardian_data[0] & 0b01111111).into();
@@ -240,10 +244,12 @@ impl TryFrom<&[u8]> for Guardian {
   }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// How often a guardian moves: normal guardians move on every tick, and slow
+/// ones on every other tick.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum GuardianSpeed {
   Normal,
-  Fast,
+  Slow,
 }
 
 #[derive(Debug)]
@@ -302,16 +308,26 @@ impl From<&[u8]> for Item {
   }
 }
 
+/// How Willy starts a cavern.
 #[derive(Debug)]
 pub struct WillyStart {
+  /// The cell Willy starts in.
   pub position: (u8, u8),
-  pub direction: Direction,
+  /// Willy's pixel y coordinate, which is stored separately from his cell.
+  pub pixel_y: u8,
+  pub walking: bool,
+  /// The first animation frame, which is 4 or more when facing left.
   pub first_animation_frame: u8,
+  /// The original's airborne status: 0 on the ground, 1 jumping, and a count
+  /// up from 2 while falling.
+  pub airborne: u8,
+  pub jump_counter: u8,
 }
 
 impl From<&[u8]> for WillyStart {
   fn from(data: &[u8]) -> WillyStart {
-    let direction = if data[2] == 0u8 {
+    // Bit 0 of the flags is set when facing left, and bit 1 when walking.
+    let direction = if data[2] & 1 == 0 {
       Direction::Right
     } else {
       Direction::Left
@@ -319,12 +335,16 @@ impl From<&[u8]> for WillyStart {
 
     WillyStart {
       position: decode_packed_position(&data[4..=5]),
-      direction,
+      // The y coordinate is stored doubled.
+      pixel_y: data[0] / 2,
+      walking: data[2] & 2 != 0,
       first_animation_frame: if direction == Direction::Right {
         data[1]
       } else {
         4 + data[1]
       },
+      airborne: data[3],
+      jump_counter: data[6],
     }
   }
 }
@@ -346,7 +366,7 @@ impl From<&[u8]> for Conveyor {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConveyorDirection {
   Left = 0,
   Right = 1,
@@ -358,6 +378,17 @@ impl ConveyorDirection {
   pub fn is_moving(&self) -> bool {
     matches!(*self, ConveyorDirection::Left | ConveyorDirection::Right | ConveyorDirection::Sticky)
   }
+
+  /// How many pixels the top of the conveyor moves to the right each tick, as
+  /// drawn in the original. Conveyors that are off don't move, and neither do
+  /// sticky ones, which don't carry Willy anywhere.
+  pub fn surface_speed(&self) -> i8 {
+    match *self {
+      ConveyorDirection::Left => -2,
+      ConveyorDirection::Right => 2,
+      ConveyorDirection::Off | ConveyorDirection::Sticky => 0,
+    }
+  }
 }
 
 impl From<u8> for ConveyorDirection {
@@ -11,7 +11,8 @@ const WILLY_SPRITE_OFFSET: u64 = 0x8200;
 const WILLY_SPRITE_SIZE_BYTES: usize = 8 * 4;
 
 const CAVERNS_OFFSET: u64 = 0xb000;
-const CAVERN_COUNT: usize = 20;
+/// How many caverns there are.
+pub const CAVERN_COUNT: usize = 20;
 const CAVERN_DATA_SIZE_BYTES: usize = 1024;
 
 #[derive(Debug)]
@@ -9,7 +9,10 @@ use bevy::prelude::*;
 
 use crate::handle_errors;
 
-use self::data::GameData;
+pub use self::data::{GameData, CAVERN_COUNT};
+
+/// The manic miner binary that game data is extracted from.
+pub const GAME_DATA_PATH: &str = "assets/ManicMiner.bin";
 
 pub struct GameDataPlugin;
 
@@ -23,7 +26,7 @@ impl Plugin for GameDataPlugin {
 }
 
 fn load_game_data(mut commands: Commands) -> Result<()> {
-  commands.insert_resource(GameDataResource(GameData::load("assets/ManicMiner.bin")?));
+  commands.insert_resource(GameDataResource(GameData::load(GAME_DATA_PATH)?));
 
   Ok(())
 }
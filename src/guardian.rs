@@ -3,66 +3,68 @@ use bevy::prelude::*;
 use crate::{
   actors::{Actor, Direction, HorizontalMotion, Sprites, update_actor_sprite},
   cavern::CurrentCavern,
-  gamedata::{cavern, GameDataResource},
-  position::{Layer, Position}, timer::GameTimer, despawn_all,
+  gamedata::{cavern::{self, GuardianSpeed}, GameDataResource},
+  image_cache::SpectrumImages,
+  position::{Layer, Position}, timer::GameTick, despawn_all,
 };
 
 pub struct GuardianPlugin;
 
 impl Plugin for GuardianPlugin {
   fn build(&self, app: &mut App) {
+    app.insert_resource(GameClock(0));
     app.add_systems(Update, (
       spawn_guardians,
       update_actor_sprite::<Guardian>,
-      move_guardians,
-      change_direction
     ));
+    app.add_systems(FixedUpdate, (move_guardians, change_direction, tick_clock).chain().in_set(GameTick));
+  }
+}
+
+/// The original game's clock, which starts at a value set by each cavern and
+/// goes down by 4 after every tick. Slow guardians only move while bit 2 of it
+/// is clear.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct GameClock(pub u8);
+
+impl GameClock {
+  pub fn tick(&mut self) {
+    self.0 = self.0.wrapping_sub(4);
   }
 }
 
 #[derive(Component, Debug)]
 pub struct Guardian {
-//  id: u8,
-  data: cavern::Guardian,
+  /// The guardian's place in the cavern's list of guardians.
+  pub id: u8,
+  pub data: cavern::Guardian,
 }
 
 fn spawn_guardians(
   mut commands: Commands,
   cavern: ResMut<CurrentCavern>,
   game_data: Res<GameDataResource>,
-  mut images: ResMut<Assets<Image>>,
+  mut images: SpectrumImages,
+  mut clock: ResMut<GameClock>,
   query: Query<Entity, With<Guardian>>
 ) {
   if cavern.is_changed() {
     despawn_all(&mut commands, query);
     let cavern_data = &game_data.caverns[cavern.number];
+    *clock = GameClock(cavern_data.clock);
 
-    // Create images for guardian sprites.
+    // Create images for guardian sprites, one for each animation frame.
 
-    for (_, g) in cavern_data.guardians.iter().enumerate() {
-      let images: Vec<_> = cavern_data
-        .guardian_bitmaps
-        .iter()
-        .map(|s| images.add(s.render_with_color(&g.attributes)))
+    for (id, g) in cavern_data.guardians.iter().enumerate() {
+      let images: Vec<_> = (0..8)
+        .map(|frame| images.get(&cavern_data.guardian_bitmaps[sprite_index(cavern.number, frame)], &g.attributes))
         .collect();
 
-      let mut position = Position::at_char_pos(Layer::Characters, g.start_pos);
-      let movement = HorizontalMotion {
-        walking: true,
-        current_frame: g.first_animation_frame as usize
-      };
-
-      // If we're initially moving left, we need to move the position to the rightmost
-      // pixel position of the cell.
-      if movement.direction() == Direction::Left {
-        position.step(Direction::Right);
-        position.step(Direction::Right);
-        position.step(Direction::Right);
-      }
+      let (position, movement) = start_position(g);
 
       commands.spawn(Actor::new(
         Guardian {
-//          id: id as u8,
+          id: id as u8,
           data: g.clone(),
         },
         position,
@@ -75,23 +77,72 @@ fn spawn_guardians(
   }
 }
 
+/// Returns where the given guardian starts, and how it's initially moving.
+pub fn start_position(guardian: &cavern::Guardian) -> (Position, HorizontalMotion) {
+  let mut position = Position::at_char_pos(Layer::Characters, guardian.start_pos);
+  let movement = HorizontalMotion {
+    walking: true,
+    current_frame: guardian.first_animation_frame as usize
+  };
+
+  // Each animation frame is two pixels further across the cell than the last,
+  // so the first frame says where in its cell the guardian starts.
+  let (x, y) = position.zx_pixel_pos();
+  position.set_zx_pixel_pos((x + 2 * (movement.current_frame % 4) as i16, y));
+
+  (position, movement)
+}
+
+/// The guardian sprite that's drawn for an animation frame in the given
+/// cavern. From the Kong Beast's cavern on, the first four sprites are mostly
+/// taken by other guardians, so horizontal guardians only use the last four
+/// whichever way they're going, apart from in The Endorian Forest and The
+/// Sixteenth Cavern.
+pub fn sprite_index(cavern: usize, frame: usize) -> usize {
+  if cavern >= 7 && cavern != 9 && cavern != 15 {
+    frame | 4
+  } else {
+    frame
+  }
+}
+
+/// Whether a guardian moves on the tick with the given game clock.
+pub fn moves_at(guardian: &cavern::Guardian, clock: GameClock) -> bool {
+  guardian.speed == GuardianSpeed::Normal || clock.0 & 4 == 0
+}
+
+/// Moves a guardian by a single game tick along its path. This is the same as
+/// what [move_guardians] followed by [change_direction] does.
+pub fn advance(motion: &mut HorizontalMotion, position: &mut Position, guardian: &cavern::Guardian, clock: GameClock) {
+  if !moves_at(guardian, clock) {
+    return;
+  }
+  motion.step(position);
+  if at_end_of_path(motion, position, guardian) {
+    motion.change_direction();
+    motion.step(position);
+  }
+}
 
 fn move_guardians(
-  timer: Res<GameTimer>,
+  clock: Res<GameClock>,
   mut query: Query<(
     &mut HorizontalMotion,
-    &mut Position
-  ),
-    With<Guardian>
-  >) {
+    &mut Position,
+    &Guardian
+  )>) {
 
-  if timer.just_finished() {
-    for (mut motion, mut pos) in query.iter_mut() {
+  for (mut motion, mut pos, guardian) in query.iter_mut() {
+    if moves_at(&guardian.data, *clock) {
       motion.step(&mut pos);
     }
   }
 }
 
+fn tick_clock(mut clock: ResMut<GameClock>) {
+  clock.tick();
+}
+
 /// Changes the Guardian's direction if it has reached the end of its
 /// path.
 #[allow(clippy::type_complexity)]
@@ -106,11 +157,67 @@ fn change_direction(mut query: Query<(
 )
 >) {
   for (mut motion, mut position, guardian) in query.iter_mut() {
-    let (x, _) = position.char_pos();
-    if (matches!(motion.direction(), Direction::Right) &&  x > guardian.data.right_bound) ||
-      (matches!(motion.direction(), Direction::Left) && x < guardian.data.left_bound) {
-        motion.change_direction();
-        motion.step(&mut position);
+    if at_end_of_path(&motion, &position, &guardian.data) {
+      motion.change_direction();
+      motion.step(&mut position);
+    }
+  }
+}
+
+fn at_end_of_path(motion: &HorizontalMotion, position: &Position, guardian: &cavern::Guardian) -> bool {
+  // One that's somehow left the screen has certainly gone past the end.
+  let Some((x, _)) = position.char_pos() else {
+    return true;
+  };
+  (matches!(motion.direction(), Direction::Right) && x > guardian.right_bound) ||
+    (matches!(motion.direction(), Direction::Left) && x < guardian.left_bound)
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::color::Attributes;
+
+  fn guardian(speed: GuardianSpeed, first_animation_frame: u8) -> cavern::Guardian {
+    cavern::Guardian {
+      attributes: Attributes::from(0x46),
+      start_pos: (8, 7),
+      first_animation_frame,
+      left_bound: 8,
+      right_bound: 15,
+      speed,
+    }
+  }
+
+  #[test]
+  fn guardians_start_part_way_across_their_cell() {
+    for (frame, x) in [(0, 64), (2, 68), (3, 70), (4, 64), (7, 70)] {
+      let (position, _) = start_position(&guardian(GuardianSpeed::Normal, frame));
+      assert_eq!(position.zx_pixel_pos(), (x, 56));
+    }
+  }
+
+  #[test]
+  fn slow_guardians_move_on_every_other_tick() {
+    for (speed, expected) in [(GuardianSpeed::Normal, [66, 68, 70, 72]), (GuardianSpeed::Slow, [64, 66, 66, 68])] {
+      let guardian = guardian(speed, 0);
+      let (mut position, mut motion) = start_position(&guardian);
+      let mut clock = GameClock(252);
+      let mut xs = vec![];
+      for _ in 0..4 {
+        advance(&mut motion, &mut position, &guardian, clock);
+        clock.tick();
+        xs.push(position.zx_pixel_pos().0);
       }
+      assert_eq!(xs, expected);
+    }
+  }
+
+  #[test]
+  fn later_caverns_share_four_sprites_between_directions() {
+    assert_eq!(sprite_index(0, 1), 1);
+    assert_eq!(sprite_index(7, 1), 5);
+    assert_eq!(sprite_index(7, 6), 6);
+    assert_eq!(sprite_index(9, 1), 1);
+    assert_eq!(sprite_index(15, 2), 2);
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,127 @@
+//! A cache of the images rendered from Spectrum bitmaps. Each image is keyed
+//! by the bitmap it was drawn from, the colors it was drawn in and any
+//! transform applied to the bitmap first, so asking for the same image twice
+//! returns the same handle rather than creating a new texture.
+//!
+//! The cache only holds weak handles, so an image is freed as usual once
+//! nothing else uses it, and is then forgotten by the cache.
+
+use std::collections::HashMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{bitmap::Bitmap, color::Attributes, palette::Palette};
+
+pub struct ImageCachePlugin;
+
+impl Plugin for ImageCachePlugin {
+  fn build(&self, app: &mut App) {
+    app.insert_resource(ImageCache::default());
+    app.add_systems(Update, (rerender_images, forget_freed_images).chain());
+  }
+}
+
+/// A change made to a bitmap before it's rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageTransform {
+  None,
+  /// Every row moved down by the given number of rows, as crumbling floors
+  /// are drawn.
+  ShiftDown(u8),
+  /// The top row rotated by the given number of pixels, and the third row
+  /// rotated the same amount the other way, as conveyors are drawn.
+  Conveyor(i8),
+}
+
+impl ImageTransform {
+  pub fn apply(&self, bitmap: &Bitmap) -> Bitmap {
+    match *self {
+      ImageTransform::None => bitmap.clone(),
+      ImageTransform::ShiftDown(rows) => (0..rows).fold(bitmap.clone(), |b, _| b.shift_down()),
+      ImageTransform::Conveyor(pixels) => {
+        let mut bitmap = bitmap.clone();
+        bitmap.rotate_row(0, pixels);
+        bitmap.rotate_row(2, -pixels);
+        bitmap
+      }
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ImageKey {
+  bitmap: Bitmap,
+  color: Attributes,
+  transform: ImageTransform,
+}
+
+/// Weak handles to every image that's been rendered, by what it was rendered
+/// from.
+#[derive(Resource, Default)]
+struct ImageCache(HashMap<ImageKey, Handle<Image>>);
+
+/// Gets images rendered from Spectrum bitmaps using the current palette.
+#[derive(SystemParam)]
+pub struct SpectrumImages<'w> {
+  images: ResMut<'w, Assets<Image>>,
+  cache: ResMut<'w, ImageCache>,
+  palette: Res<'w, Palette>,
+}
+
+impl SpectrumImages<'_> {
+  pub fn get(&mut self, bitmap: &Bitmap, color: &Attributes) -> Handle<Image> {
+    self.get_transformed(bitmap, color, ImageTransform::None)
+  }
+
+  pub fn get_transformed(
+    &mut self,
+    bitmap: &Bitmap,
+    color: &Attributes,
+    transform: ImageTransform,
+  ) -> Handle<Image> {
+    let key = ImageKey {
+      bitmap: bitmap.clone(),
+      color: *color,
+      transform,
+    };
+
+    if let Some(handle) = self.cache.0.get(&key) {
+      if self.images.contains(handle) {
+        return self.images.get_handle(handle);
+      }
+    }
+
+    let handle = self.images.add(transform.apply(bitmap).render_with_color(color, &self.palette));
+    self.cache.0.insert(key, handle.clone_weak());
+
+    handle
+  }
+}
+
+fn rerender_images(palette: Res<Palette>, cache: Res<ImageCache>, mut images: ResMut<Assets<Image>>) {
+  if palette.is_changed() && !palette.is_added() {
+    for (key, handle) in cache.0.iter() {
+      if let Some(image) = images.get_mut(handle) {
+        image.data = key.transform.apply(&key.bitmap).render_to_rgba(&key.color, &palette);
+      }
+    }
+  }
+}
+
+fn forget_freed_images(mut cache: ResMut<ImageCache>, images: Res<Assets<Image>>) {
+  cache.0.retain(|_, handle| images.contains(handle));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn transforms_move_pixels() {
+    let bitmap = Bitmap::create(8, 8, &[0b11000000, 0, 0b11000000, 0, 0, 0, 0, 0]);
+    let moved = ImageTransform::Conveyor(2).apply(&bitmap);
+
+    assert_eq!(&moved.data()[0..3], &[0b00110000, 0, 0b00000011]);
+    assert_eq!(ImageTransform::ShiftDown(2).apply(&bitmap), bitmap.shift_down().shift_down());
+  }
+}
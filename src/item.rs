@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{gamedata::GameDataResource, cavern::CurrentCavern, actors::{Actor, HorizontalMotion, Sprites, update_actor_sprite}, position::Position, bitmap::Bitmap, color::{Attributes, ColorName}, timer::GameTimer, clamp, despawn_all};
+use crate::{gamedata::GameDataResource, cavern::CurrentCavern, actors::{Actor, HorizontalMotion, Sprites, update_actor_sprite}, position::Position, bitmap::Bitmap, color::{Attributes, ColorName}, image_cache::SpectrumImages, timer::GameTick, clamp, despawn_all};
 
 pub struct ItemPlugin;
 
@@ -9,9 +9,9 @@ impl Plugin for ItemPlugin {
     app.add_systems(Update, (
       update_actor_sprite::<Item>,
       spawn_items,
-      cycle_items,
-      despawn_when_collected,
+      hide_when_collected,
     ).chain());
+    app.add_systems(FixedUpdate, cycle_items.in_set(GameTick));
   }
 }
 
@@ -23,7 +23,7 @@ pub struct Item {
 
 fn spawn_items(
     mut commands: Commands,
-    mut images: ResMut<Assets<Image>>,
+    mut images: SpectrumImages,
     cavern: ResMut<CurrentCavern>,
     game_data: Res<GameDataResource>,
     query: Query<Entity, With<Item>>
@@ -33,9 +33,7 @@ fn spawn_items(
     let cavern_data = &game_data.caverns[cavern.number];
 
     for item in cavern_data.items.iter() {
-      let images = create_cycle_images(&cavern_data.item_bitmap, &item.attributes)
-          .into_iter()
-          .map(|a| images.add(a)).collect();
+      let images = create_cycle_images(&mut images, &cavern_data.item_bitmap, &item.attributes);
 
       commands.spawn(Actor::new(
         Item {
@@ -52,47 +50,44 @@ fn spawn_items(
   }
 }
 
-fn cycle_items(timer: Res<GameTimer>, mut query: Query<&mut HorizontalMotion, With<Item>>) {
-  if timer.just_finished() {
-    for mut motion in query.iter_mut() {
-      motion.current_frame = clamp(motion.current_frame + 1, 0, 3);
-    }
+fn cycle_items(mut query: Query<&mut HorizontalMotion, With<Item>>) {
+  for mut motion in query.iter_mut() {
+    motion.current_frame = clamp(motion.current_frame + 1, 0, 3);
   }
 }
 
 
-fn create_cycle_images(bitmap: &Bitmap, initial_color: &Attributes) -> Vec<Image> {
-  let mut images = Vec::with_capacity(4);
-
-  // Find the starting index in COLOR_SEQUENCE based on the initial_color.
-  let mut start_index = None;
-  for (index, color) in COLOR_SEQUENCE.iter().enumerate() {
-    let color_u8 : u8 = (*color).into();
-    if color_u8 == initial_color.ink {
-      start_index = Some(index);
-      break;
-    }
-  }
+fn create_cycle_images(
+    images: &mut SpectrumImages,
+    bitmap: &Bitmap,
+    initial_color: &Attributes
+) -> Vec<Handle<Image>> {
+  (0..4)
+    .map(|frame| images.get(bitmap, &cycle_color(initial_color, frame)))
+    .collect()
+}
 
+/// Returns the colors an item that started with the given colors has after
+/// `frame` steps of its animation.
+pub fn cycle_color(initial_color: &Attributes, frame: usize) -> Attributes {
   // Snap back to magenta in case the ink color isn't one of the colors in the
   // sequence.
-  let mut index = start_index.unwrap_or(0);
-  for _ in 0..4 {
-    let mut attributes = *initial_color;
-    attributes.ink = COLOR_SEQUENCE[index].into();
-    images.push(bitmap.render_with_color(&attributes));
-
-    index = clamp(index + 1, 0, 3);
+  let start_index = COLOR_SEQUENCE
+    .iter()
+    .position(|color| u8::from(*color) == initial_color.ink)
+    .unwrap_or(0);
+
+  Attributes {
+    ink: COLOR_SEQUENCE[(start_index + frame) % 4].into(),
+    ..*initial_color
   }
-
-  images
 }
 
-fn despawn_when_collected(mut commands: Commands, query: Query<(&Item, Entity), Changed<Item>>) {
-  for (item, entity) in query.iter() {
-    if item.collected {
-      commands.entity(entity).despawn();
-    }
+/// Hides collected items. They're kept around rather than despawned, so that
+/// rewinding can bring them back.
+fn hide_when_collected(mut query: Query<(&Item, &mut Visibility), Changed<Item>>) {
+  for (item, mut visibility) in query.iter_mut() {
+    *visibility = if item.collected { Visibility::Hidden } else { Visibility::Inherited };
   }
 }
 
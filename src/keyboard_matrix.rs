@@ -0,0 +1,182 @@
+//! The Spectrum's keyboard, for the authentic input mode. The hardware reads
+//! the 40 keys as 8 half-rows of 5, selected by the high byte of the port
+//! address, and the original game decides what Willy does from whole
+//! half-rows rather than from single keys:
+//!
+//! * Q, E, T, U and O move left, and W, R, Y, I and P move right.
+//! * Any key on the bottom row jumps.
+//! * Any key from A to G pauses, and any key from H to ENTER turns the tune
+//!   on and off.
+//!
+//! In authentic mode these replace the bound keys for those actions, though
+//! gamepads work as usual.
+
+use bevy::prelude::*;
+
+use crate::{actions::Action, config::Config};
+
+/// The keys of each half-row, in bit order, for the port addresses 0xfefe,
+/// 0xfdfe, 0xfbfe and so on.
+const HALF_ROWS: [[KeyCode; 5]; 8] = [
+  [KeyCode::ShiftLeft, KeyCode::Z, KeyCode::X, KeyCode::C, KeyCode::V],
+  [KeyCode::A, KeyCode::S, KeyCode::D, KeyCode::F, KeyCode::G],
+  [KeyCode::Q, KeyCode::W, KeyCode::E, KeyCode::R, KeyCode::T],
+  [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5],
+  [KeyCode::Key0, KeyCode::Key9, KeyCode::Key8, KeyCode::Key7, KeyCode::Key6],
+  [KeyCode::P, KeyCode::O, KeyCode::I, KeyCode::U, KeyCode::Y],
+  [KeyCode::Return, KeyCode::L, KeyCode::K, KeyCode::J, KeyCode::H],
+  [KeyCode::Space, KeyCode::ShiftRight, KeyCode::M, KeyCode::N, KeyCode::B],
+];
+
+/// PC keys that stand in for Spectrum keys that aren't on a PC keyboard, with
+/// the half-row and bit of the key they stand in for.
+const EXTRA_KEYS: [(KeyCode, usize, u8); 2] = [
+  // Symbol shift
+  (KeyCode::ControlLeft, 7, 1),
+  (KeyCode::ControlRight, 7, 1),
+];
+
+/// How the keyboard controls Willy.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+  /// The keys bound to each action.
+  #[default]
+  Standard,
+  /// The original game's half-rows of the Spectrum keyboard.
+  Authentic,
+}
+
+impl InputMode {
+  const SETTING: &'static str = "input.mode";
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      InputMode::Standard => "standard",
+      InputMode::Authentic => "authentic",
+    }
+  }
+
+  pub fn toggle(&mut self) {
+    *self = match self {
+      InputMode::Standard => InputMode::Authentic,
+      InputMode::Authentic => InputMode::Standard,
+    };
+  }
+
+  pub fn save_to(&self, config: &mut Config) {
+    config.set(InputMode::SETTING, self.name().to_owned());
+  }
+}
+
+impl From<&Config> for InputMode {
+  fn from(config: &Config) -> Self {
+    match config.get(InputMode::SETTING) {
+      Some("authentic") => InputMode::Authentic,
+      _ => InputMode::Standard,
+    }
+  }
+}
+
+/// The keys held down on the Spectrum keyboard, as a byte for each half-row
+/// with a bit set for each key held.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Matrix([u8; 8]);
+
+impl Matrix {
+  pub fn from_keys<'a>(keys: impl IntoIterator<Item = &'a KeyCode>) -> Self {
+    let mut matrix = Matrix::default();
+
+    for key in keys {
+      for (row, half_row) in HALF_ROWS.iter().enumerate() {
+        if let Some(bit) = half_row.iter().position(|k| k == key) {
+          matrix.0[row] |= 1 << bit;
+        }
+      }
+      for (k, row, bit) in EXTRA_KEYS {
+        if k == *key {
+          matrix.0[row] |= 1 << bit;
+        }
+      }
+    }
+
+    matrix
+  }
+
+  /// The keys held now.
+  pub fn current(keys: &Input<KeyCode>) -> Self {
+    Matrix::from_keys(keys.get_pressed())
+  }
+
+  /// The keys that were held on the previous frame.
+  pub fn previous(keys: &Input<KeyCode>) -> Self {
+    Matrix::from_keys(
+      keys.get_pressed().filter(|key| !keys.just_pressed(**key)).chain(keys.get_just_released()),
+    )
+  }
+
+  /// Reads the keyboard as `IN A,(C)` does with the given high byte of the
+  /// port. Each bit that's clear in the high byte selects a half-row, and bits
+  /// 0-4 of the result are clear for each key held in any selected half-row.
+  pub fn read(&self, high: u8) -> u8 {
+    let held = (0..8).filter(|row| high & (1 << row) == 0).fold(0, |held, row| held | self.0[row]);
+    !held | 0b11100000
+  }
+
+  /// Whether an action is being performed, for the actions the original game
+  /// reads from the keyboard.
+  pub fn action(&self, action: Action) -> Option<bool> {
+    let any_key = |high| self.read(high) & 0b11111 != 0b11111;
+
+    match action {
+      Action::Left => Some(self.movement().0),
+      Action::Right => Some(self.movement().1),
+      Action::Jump => Some(any_key(0x7e)),
+      Action::Pause => Some(any_key(0xfd)),
+      Action::Mute => Some(any_key(0xbf)),
+      _ => None,
+    }
+  }
+
+  /// Whether left and right are being pressed. The game combines the P-Y and
+  /// Q-T half-rows, with Q-T shifted along a bit, so that alternate keys along
+  /// the top row line up as left and right.
+  fn movement(&self) -> (bool, bool) {
+    let keys = ((self.read(0xdf) & 0b11111) | 0b100000) & (((self.read(0xfb) & 0b11111) << 1) | 1);
+    (keys & 0b101010 != 0b101010, keys & 0b010101 != 0b010101)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn action(keys: &[KeyCode], action: Action) -> bool {
+    Matrix::from_keys(keys).action(action).unwrap()
+  }
+
+  #[test]
+  fn top_row_alternates_left_and_right() {
+    for key in [KeyCode::Q, KeyCode::E, KeyCode::T, KeyCode::U, KeyCode::O] {
+      assert!(action(&[key], Action::Left));
+      assert!(!action(&[key], Action::Right));
+    }
+    for key in [KeyCode::W, KeyCode::R, KeyCode::Y, KeyCode::I, KeyCode::P] {
+      assert!(action(&[key], Action::Right));
+      assert!(!action(&[key], Action::Left));
+    }
+    assert!(action(&[KeyCode::Q, KeyCode::P], Action::Left));
+    assert!(action(&[KeyCode::Q, KeyCode::P], Action::Right));
+  }
+
+  #[test]
+  fn half_rows_jump_pause_and_mute() {
+    assert!(action(&[KeyCode::ShiftLeft], Action::Jump));
+    assert!(action(&[KeyCode::B], Action::Jump));
+    assert!(action(&[KeyCode::ControlLeft], Action::Jump));
+    assert!(!action(&[KeyCode::A], Action::Jump));
+    assert!(action(&[KeyCode::G], Action::Pause));
+    assert!(action(&[KeyCode::Return], Action::Mute));
+    assert!(!action(&[KeyCode::Left], Action::Left));
+    assert_eq!(Matrix::default().action(Action::Start), None);
+  }
+}
@@ -5,12 +5,17 @@ use bevy::{prelude::*, sprite::Anchor};
 use crate::{
   color::{Attributes, ColorName},
   gamedata::GameDataResource,
+  image_cache::SpectrumImages,
   position::{Layer, Position},
   text::{Text, TextAttributes},
+  GameState,
 };
 
 static LIVES_TIMER_TICK: f32 = 0.3;
 
+/// How many lives Willy has at the start of a game.
+pub const STARTING_LIVES: u8 = 3;
+
 pub struct LivesPlugin;
 
 #[derive(Resource)]
@@ -20,6 +25,13 @@ pub struct Lives {
   animation_timer: Timer,
 }
 
+impl Lives {
+  /// Gives Willy a full set of lives for a new game.
+  pub fn reset(&mut self) {
+    self.lives_remaining = STARTING_LIVES;
+  }
+}
+
 #[derive(Resource, Deref)]
 struct Textures(Vec<Handle<Image>>);
 
@@ -29,17 +41,18 @@ struct LifeSprite;
 impl Plugin for LivesPlugin {
   fn build(&self, app: &mut App) {
     app.add_systems(Startup, setup);
-    app.add_systems(Update, update_life_sprites);
+    // Like everything else, the lives stop walking while the game is paused.
+    app.add_systems(Update, update_life_sprites.run_if(in_state(GameState::Playing)));
   }
 }
 
 fn setup(
   mut commands: Commands,
   game_data: Res<GameDataResource>,
-  mut images: ResMut<Assets<Image>>,
+  mut images: SpectrumImages,
 ) {
   commands.insert_resource(Lives {
-    lives_remaining: 3,
+    lives_remaining: STARTING_LIVES,
     current_animation_frame: 0,
     animation_timer: Timer::from_seconds(LIVES_TIMER_TICK, TimerMode::Repeating),
   });
@@ -50,7 +63,7 @@ fn setup(
   let mut images: Vec<_> = game_data
     .willy_sprites
     .iter()
-    .map(|s| images.add(s.render_with_color(&color)))
+    .map(|s| images.get(s, &color))
     .collect();
   // We only need the first 4 textures, which are the walking right animation.
   images.truncate(4);
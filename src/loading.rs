@@ -0,0 +1,107 @@
+//! The loading screen, which is shown before the title screen when the game
+//! starts, like the picture the original showed while it loaded from tape. It
+//! is any Spectrum `.scr` file saved as [LOADING_SCREEN_PATH], and if there
+//! isn't one, the game goes straight to the title screen.
+
+use std::path::Path;
+
+use anyhow::Result;
+use bevy::{
+  prelude::*,
+  render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+  sprite::Anchor,
+};
+
+use crate::{
+  actions::{Action, Actions},
+  despawn_all, handle_errors,
+  palette::Palette,
+  position::{Layer, Position},
+  render::{SCREEN_HEIGHT, SCREEN_WIDTH},
+  scr::Screen,
+  GameState,
+};
+
+/// Where the loading screen is loaded from.
+pub const LOADING_SCREEN_PATH: &str = "assets/loading.scr";
+
+/// How long the loading screen is shown for, unless the start key is pressed.
+const LOADING_SCREEN_SECONDS: f32 = 3.;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+  fn build(&self, app: &mut App) {
+    app.add_systems(OnEnter(GameState::Loading), show_loading_screen.pipe(handle_errors));
+    app.add_systems(OnExit(GameState::Loading), hide_loading_screen);
+    app.add_systems(Update, wait_for_title.run_if(in_state(GameState::Loading)));
+  }
+}
+
+/// The sprite showing the loading screen.
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Resource, Deref, DerefMut)]
+struct LoadingTimer(Timer);
+
+fn show_loading_screen(
+  mut commands: Commands,
+  palette: Res<Palette>,
+  mut images: ResMut<Assets<Image>>,
+  mut next_state: ResMut<NextState<GameState>>,
+) -> Result<()> {
+  if !Path::new(LOADING_SCREEN_PATH).exists() {
+    next_state.set(GameState::Title);
+    return Ok(());
+  }
+
+  // Whatever happens, the game carries on to the title screen.
+  commands.insert_resource(LoadingTimer(Timer::from_seconds(LOADING_SCREEN_SECONDS, TimerMode::Once)));
+  let screen = Screen::load(LOADING_SCREEN_PATH)?;
+
+  let image = Image::new(
+    Extent3d {
+      width: SCREEN_WIDTH as u32,
+      height: SCREEN_HEIGHT as u32,
+      depth_or_array_layers: 1,
+    },
+    TextureDimension::D2,
+    screen.render_to_rgba(&palette),
+    TextureFormat::Rgba8Unorm,
+  );
+  commands.spawn((
+    LoadingScreen,
+    SpriteBundle {
+      sprite: Sprite {
+        anchor: Anchor::TopLeft,
+        ..default()
+      },
+      texture: images.add(image),
+      transform: Position::at_char_pos(Layer::Overlay, (0, 0)).into(),
+      ..default()
+    },
+  ));
+
+  Ok(())
+}
+
+fn wait_for_title(
+  time: Res<Time>,
+  actions: Actions,
+  timer: Option<ResMut<LoadingTimer>>,
+  mut next_state: ResMut<NextState<GameState>>,
+) {
+  let Some(mut timer) = timer else {
+    return;
+  };
+
+  if timer.tick(time.delta()).finished() || actions.just_released(Action::Start) {
+    next_state.set(GameState::Title);
+  }
+}
+
+fn hide_loading_screen(mut commands: Commands, query: Query<Entity, With<LoadingScreen>>) {
+  commands.remove_resource::<LoadingTimer>();
+  despawn_all(&mut commands, query);
+}
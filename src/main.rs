@@ -1,41 +1,68 @@
 
 
+use actions::ActionsPlugin;
 use air::AirPlugin;
 use anyhow::Result;
 use bevy::prelude::*;
 use cavern::CavernPlugin;
+use clash::ClashPlugin;
+use config::ConfigPlugin;
 use debug::DebugPlugin;
+use define_keys::DefineKeysPlugin;
 use gamedata::GameDataPlugin;
 use guardian::GuardianPlugin;
+use image_cache::ImageCachePlugin;
 use item::ItemPlugin;
 use lives::LivesPlugin;
+use loading::LoadingPlugin;
+use palette::PalettePlugin;
+use pause::PausePlugin;
 use portal::PortalPlugin;
+use save_state::SaveStatePlugin;
 use score::ScorePlugin;
 use text::TextPlugin;
+use time_control::TimeControlPlugin;
 use timer::TimerPlugin;
+use title::TitlePlugin;
 use willy::WillyPlugin;
 
+mod actions;
 mod air;
 mod actors;
 mod bitmap;
+mod bot;
 mod cavern;
+mod clash;
 mod color;
+mod commands;
+mod config;
 mod debug;
+mod define_keys;
 mod item;
+mod keyboard_matrix;
 mod gamedata;
 mod guardian;
+mod image_cache;
 mod lives;
+mod loading;
+mod palette;
+mod pause;
 mod portal;
 mod position;
+mod reachability;
+mod render;
+mod save_state;
 mod score;
+mod scr;
+mod simulation;
 mod text;
+mod time_control;
 mod timer;
+mod title;
 mod willy;
 
 pub static SCALE: f32 = 2.0;
 //static CELLSIZE: f32 = 8.0 * SCALE;
-// static TIMER_TICK: f32 = 0.07;
-// slow mo static TIMER_TICK: f32 = 0.25;
 static BORDER_WIDTH_CHARS: f32 = 4.;
 
 static DISPLAY_SCREEN_WIDTH_CH: f32 = 32.;
@@ -48,6 +75,18 @@ static WINDOW_HEIGHT_PX: f32 = SCREEN_HEIGHT_PX + (BORDER_WIDTH_PX * BORDER_MUL)
 static PIX_PER_CHAR: f32 = 8.;
 static BORDER_MUL: f32 = 2.;
 
+/// Which screen the game is showing.
+#[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+  /// The loading screen, if there is one.
+  #[default]
+  Loading,
+  Title,
+  Playing,
+  Paused,
+  DefineKeys,
+}
+
 pub fn handle_errors(In(result): In<Result<()>>) {
   if let Err(e) = result {
     eprintln!("Error: {}", e);
@@ -55,6 +94,12 @@ pub fn handle_errors(In(result): In<Result<()>>) {
 }
 
 fn main() -> Result<()> {
+  // Anything on the command line is a subcommand that runs without a window.
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if let Some(command) = args.first() {
+    return commands::run(command, &args[1..]);
+  }
+
   let window = Window {
     title: "Miner Willy".into(),
     resolution: (WINDOW_WIDTH_PX, WINDOW_HEIGHT_PX).into(),
@@ -73,6 +118,8 @@ fn main() -> Result<()> {
     .add_plugins((
       TimerPlugin,
       DebugPlugin,
+      PalettePlugin,
+      ImageCachePlugin,
       GameDataPlugin,
       CavernPlugin,
       TextPlugin,
@@ -82,8 +129,20 @@ fn main() -> Result<()> {
       LivesPlugin,
       GuardianPlugin,
       PortalPlugin,
-      ItemPlugin
+      ItemPlugin,
+      ClashPlugin
+    ))
+    .add_plugins((
+      ConfigPlugin,
+      ActionsPlugin,
+      LoadingPlugin,
+      TitlePlugin,
+      DefineKeysPlugin,
+      PausePlugin,
+      TimeControlPlugin,
+      SaveStatePlugin,
     ))
+    .add_state::<GameState>()
     .add_systems(PostStartup, setup)
     .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
     .run();
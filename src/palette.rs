@@ -0,0 +1,157 @@
+//! The rgb values that Spectrum colors are drawn with. There are several
+//! presets to choose from; press K (or whichever key is bound to
+//! [Action::CyclePalette]) to cycle through them while playing.
+//!
+//! Images made through [crate::image_cache::SpectrumImages] are redrawn in
+//! place when the palette changes.
+
+use bevy::prelude::*;
+
+use crate::actions::{Action, Actions};
+
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+  fn build(&self, app: &mut App) {
+    app.insert_resource(Palette::default());
+    app.add_systems(Update, check_keyboard);
+  }
+}
+
+/// The rgb values for each of the 8 Spectrum colors, at normal and bright
+/// intensity.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Palette {
+  pub name: &'static str,
+  normal: [[u8; 3]; 8],
+  bright: [[u8; 3]; 8],
+}
+
+impl Default for Palette {
+  fn default() -> Self {
+    Palette::with_intensities("default", 0xee, 0xff)
+  }
+}
+
+impl Palette {
+  /// Every preset palette, starting with the default one.
+  pub fn presets() -> Vec<Palette> {
+    // Emulators commonly draw the Spectrum's colors at one of these two
+    // intensities. Neither is measured from a real Spectrum's output.
+    let emulator = Palette::with_intensities("emulator", 0xd7, 0xff);
+
+    vec![
+      Palette::default(),
+      Palette::greyscale("greyscale", &emulator),
+      emulator,
+      Palette::with_intensities("emulator-dark", 0xc0, 0xff),
+      // Based on the palette from Okabe & Ito's "Color Universal Design", which
+      // stays distinguishable with the common forms of color blindness.
+      Palette::from_bright(
+        "deuteranopia",
+        [0x000000, 0x0072b2, 0xd55e00, 0xcc79a7, 0x009e73, 0x56b4e9, 0xf0e442, 0xffffff],
+      ),
+      // Red looks very dark with protanopia, so it's swapped for orange.
+      Palette::from_bright(
+        "protanopia",
+        [0x000000, 0x0072b2, 0xe69f00, 0xcc79a7, 0x009e73, 0x56b4e9, 0xf0e442, 0xffffff],
+      ),
+    ]
+  }
+
+  /// Finds the preset with the given name.
+  pub fn named(name: &str) -> Option<Palette> {
+    Palette::presets().into_iter().find(|p| p.name == name)
+  }
+
+  /// A palette where each color is made up of full red, green and blue
+  /// components, depending on the bits of the color, at the given intensities.
+  fn with_intensities(name: &'static str, normal: u8, bright: u8) -> Self {
+    let color = |value: usize, intensity: u8| {
+      [
+        if value & 0b010 != 0 { intensity } else { 0 },
+        if value & 0b100 != 0 { intensity } else { 0 },
+        if value & 0b001 != 0 { intensity } else { 0 },
+      ]
+    };
+
+    Palette {
+      name,
+      normal: std::array::from_fn(|i| color(i, normal)),
+      bright: std::array::from_fn(|i| color(i, bright)),
+    }
+  }
+
+  /// A palette from the given bright colors (as 0xrrggbb), with normal colors
+  /// being a bit darker.
+  fn from_bright(name: &'static str, bright: [u32; 8]) -> Self {
+    let bright = bright.map(|c| [(c >> 16) as u8, (c >> 8) as u8, c as u8]);
+
+    Palette {
+      name,
+      normal: bright.map(|c| c.map(|v| (v as u32 * 0xd7 / 0xff) as u8)),
+      bright,
+    }
+  }
+
+  /// The given palette, but with every color converted to grey.
+  fn greyscale(name: &'static str, palette: &Palette) -> Self {
+    let grey = |[r, g, b]: [u8; 3]| {
+      let luma = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+      [luma as u8; 3]
+    };
+
+    Palette {
+      name,
+      normal: palette.normal.map(grey),
+      bright: palette.bright.map(grey),
+    }
+  }
+
+  /// Returns the rgba value of the given color index 0-7.
+  pub fn rgba(&self, value: u8, bright: bool) -> [u8; 4] {
+    let [r, g, b] = if bright { self.bright } else { self.normal }[value as usize & 0b111];
+    [r, g, b, 255]
+  }
+
+  /// Returns the color index and bright flag that give the given rgba value,
+  /// if there are any. Black is reported as not bright.
+  pub fn find(&self, rgba: &[u8]) -> Option<(u8, bool)> {
+    (0..16)
+      .map(|i| (i as u8 % 8, i >= 8))
+      .find(|(value, bright)| self.rgba(*value, *bright)[..3] == rgba[..3])
+  }
+}
+
+fn check_keyboard(actions: Actions, mut palette: ResMut<Palette>) {
+  if actions.just_released(Action::CyclePalette) {
+    let presets = Palette::presets();
+    let index = presets.iter().position(|p| p.name == palette.name).unwrap_or(0);
+    *palette = presets[(index + 1) % presets.len()].clone();
+    info!("Palette: {}", palette.name);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_palette_matches_original_colors() {
+    let palette = Palette::default();
+
+    assert_eq!(palette.rgba(0, false), [0x00, 0x00, 0x00, 0xff]);
+    assert_eq!(palette.rgba(2, true), [0xff, 0x00, 0x00, 0xff]);
+    assert_eq!(palette.rgba(5, false), [0x00, 0xee, 0xee, 0xff]);
+    assert_eq!(palette.find(&[0x00, 0xee, 0xee, 0xff]), Some((5, false)));
+  }
+
+  #[test]
+  fn presets_have_unique_names() {
+    let presets = Palette::presets();
+
+    for preset in presets.iter() {
+      assert_eq!(Palette::named(preset.name).as_ref(), Some(preset));
+    }
+  }
+}
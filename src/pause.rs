@@ -0,0 +1,156 @@
+//! Pausing the game. While paused everything is frozen and, as in the
+//! original, the border cycles through the colors. The game can be carried on
+//! or quit back to the title screen.
+//!
+//! The tune can be turned off and on here too, or while playing. There's no
+//! tune yet, so for now that just sets [Muted], which the pause message shows.
+
+use bevy::prelude::*;
+
+use crate::{
+  actions::{Action, Actions, KeyBindings},
+  cavern::{border_color, CurrentCavern},
+  color::{Attributes, ColorName},
+  despawn_all,
+  gamedata::GameDataResource,
+  lives::Lives,
+  palette::Palette,
+  position::Layer,
+  score::Score,
+  text::{Text, TextAttributes},
+  GameState,
+};
+
+/// How long the border shows each color for while paused.
+const BORDER_CYCLE_SECONDS: f32 = 4. / 50.;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+  fn build(&self, app: &mut App) {
+    app.insert_resource(BorderCycle {
+      timer: Timer::from_seconds(BORDER_CYCLE_SECONDS, TimerMode::Repeating),
+      color: 0,
+    });
+    app.insert_resource(Muted::default());
+    app.add_systems(OnEnter(GameState::Paused), show_pause);
+    app.add_systems(OnExit(GameState::Paused), (hide_pause, restore_border));
+    app.add_systems(Update, check_pause_keyboard.run_if(in_state(GameState::Playing)));
+    app.add_systems(Update, (check_keyboard, cycle_border).run_if(in_state(GameState::Paused)));
+    app.add_systems(
+      Update,
+      (toggle_mute, update_mute_line.after(toggle_mute))
+        .run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+    );
+  }
+}
+
+/// Whether the tune has been turned off.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Muted(pub bool);
+
+/// Everything that makes up the pause message.
+#[derive(Component)]
+struct PauseScreen;
+
+/// The line of the pause message that says whether the tune is on.
+#[derive(Component)]
+struct MuteLine;
+
+#[derive(Resource)]
+struct BorderCycle {
+  timer: Timer,
+  color: u8,
+}
+
+/// The width of the pause message, in characters.
+const WIDTH: usize = 20;
+
+fn show_pause(mut commands: Commands, bindings: Res<KeyBindings>, muted: Res<Muted>) {
+  let line = |value: String, row: u8, attributes: TextAttributes| {
+    (PauseScreen, Text::new_with_layer(&format!("{:^WIDTH$}", value), (6, row), &attributes, Layer::Overlay))
+  };
+  let white = TextAttributes::new_bright(ColorName::White, ColorName::Blue);
+  let flashing = TextAttributes::new_bright(ColorName::Yellow, ColorName::Blue).flashing();
+  let describe = |action: Action| format!("{} = {}", bindings.describe(action), action.name());
+
+  commands.spawn_batch([
+    line(String::new(), 8, white),
+    line("PAUSED".to_owned(), 9, flashing),
+    line(String::new(), 10, white),
+    line(describe(Action::Pause), 11, white),
+    line(describe(Action::Quit), 12, white),
+    line(String::new(), 14, white),
+  ]);
+  commands.spawn((MuteLine, line(mute_line(&bindings, *muted), 13, white)));
+}
+
+/// Says how to turn the tune on or off, whichever it isn't.
+fn mute_line(bindings: &KeyBindings, muted: Muted) -> String {
+  let change = if muted.0 { "Tune on" } else { "Tune off" };
+  format!("{:^WIDTH$}", format!("{} = {}", bindings.describe(Action::Mute), change))
+}
+
+fn hide_pause(mut commands: Commands, query: Query<Entity, With<PauseScreen>>) {
+  despawn_all(&mut commands, query);
+}
+
+fn check_pause_keyboard(actions: Actions, mut next_state: ResMut<NextState<GameState>>) {
+  if actions.just_pressed(Action::Pause) {
+    next_state.set(GameState::Paused);
+  }
+}
+
+fn toggle_mute(actions: Actions, mut muted: ResMut<Muted>) {
+  if actions.just_pressed(Action::Mute) {
+    muted.0 = !muted.0;
+    info!("Tune {}", if muted.0 { "off" } else { "on" });
+  }
+}
+
+fn update_mute_line(bindings: Res<KeyBindings>, muted: Res<Muted>, mut query: Query<&mut Text, With<MuteLine>>) {
+  if muted.is_changed() {
+    for mut text in query.iter_mut() {
+      text.value = mute_line(&bindings, *muted);
+    }
+  }
+}
+
+fn check_keyboard(
+  actions: Actions,
+  mut next_state: ResMut<NextState<GameState>>,
+  mut cavern: ResMut<CurrentCavern>,
+  mut score: ResMut<Score>,
+  mut lives: ResMut<Lives>,
+) {
+  if actions.just_pressed(Action::Pause) {
+    next_state.set(GameState::Playing);
+  } else if actions.just_pressed(Action::Quit) {
+    // Start again from the first cavern next time.
+    cavern.number = 0;
+    score.reset();
+    lives.reset();
+    next_state.set(GameState::Title);
+  }
+}
+
+fn cycle_border(
+  time: Res<Time>,
+  palette: Res<Palette>,
+  mut cycle: ResMut<BorderCycle>,
+  mut clear_color: ResMut<ClearColor>,
+) {
+  if cycle.timer.tick(time.delta()).just_finished() {
+    cycle.color = (cycle.color + 1) % 8;
+    clear_color.0 = Attributes::new(cycle.color.into(), ColorName::Black, false).ink_color(&palette);
+  }
+}
+
+fn restore_border(
+  game_data: Res<GameDataResource>,
+  cavern: Res<CurrentCavern>,
+  palette: Res<Palette>,
+  mut clear_color: ResMut<ClearColor>,
+) {
+  clear_color.0 = border_color(&game_data, &cavern, &palette);
+}
@@ -1,6 +1,6 @@
 use bevy::{prelude::*, sprite::Anchor};
 
-use crate::{position::{Position, Layer}, cavern::CurrentCavern, gamedata::{GameDataResource, self}, timer::GameTimer, despawn_all};
+use crate::{actions::{Action, Actions}, position::{Position, Layer}, cavern::CurrentCavern, gamedata::{GameDataResource, self}, image_cache::SpectrumImages, timer::GameTick, despawn_all};
 
 /// The number of timer ticks between flashes of the portal.
 const TICKS_PER_FLASH: usize = 4;
@@ -9,7 +9,8 @@ pub struct PortalPlugin;
 
 impl Plugin for PortalPlugin {
   fn build(&self, app: &mut bevy::prelude::App) {
-    app.add_systems(Update, (spawn_portal, check_debug_keyboard, flash_if_unlocked));
+    app.add_systems(Update, (spawn_portal, check_debug_keyboard));
+    app.add_systems(FixedUpdate, flash_if_unlocked.in_set(GameTick));
   }
 }
 
@@ -28,14 +29,14 @@ fn spawn_portal(
     mut commands: Commands,
     cavern: ResMut<CurrentCavern>,
     game_data: Res<GameDataResource>,
-    images: ResMut<Assets<Image>>,
+    mut images: SpectrumImages,
     query: Query<Entity, With<Portal>>) {
   if cavern.is_changed() {
     despawn_all(&mut commands, query);
     let portal_data = &game_data.caverns[cavern.number].portal;
 
     // todo: don't splat this sprite code all over the place
-    commands.spawn(PortalBundle::new(images, portal_data));
+    commands.spawn(PortalBundle::new(&mut images, portal_data));
   }
 }
 
@@ -46,13 +47,11 @@ struct PortalBundle {
 }
 
 impl PortalBundle {
-  fn new(mut images: ResMut<Assets<Image>>, portal_data: &gamedata::cavern::Portal) -> Self {
+  fn new(images: &mut SpectrumImages, portal_data: &gamedata::cavern::Portal) -> Self {
     let position = Position::at_char_pos(Layer::Portal, portal_data.position);
 
-    let normal_image = images.add(
-        portal_data.bitmap.render_with_color(&portal_data.attributes));
-    let inverse_image = images.add(
-        portal_data.bitmap.render_with_color(&portal_data.attributes.inverse()));
+    let normal_image = images.get(&portal_data.bitmap, &portal_data.attributes);
+    let inverse_image = images.get(&portal_data.bitmap, &portal_data.attributes.inverse());
 
     let sprite = SpriteBundle {
       sprite: Sprite {
@@ -81,23 +80,28 @@ impl PortalBundle {
   }
 }
 
-fn flash_if_unlocked(timer: Res<GameTimer>, mut query: Query<(&mut Portal, &mut Handle<Image>)>) {
-  if timer.just_finished() {
-    for (mut portal, mut image) in query.iter_mut() {
-      if portal.unlocked {
-        portal.countdown -= 1;
-        if portal.countdown == 0 {
-          portal.inverted = !portal.inverted;
-          *image = if portal.inverted { portal.inverse_image.clone() } else { portal.normal_image.clone() };
-          portal.countdown = TICKS_PER_FLASH;
-        }
+impl Portal {
+  /// Returns true if the portal is currently flashing with its colors inverted.
+  pub fn is_inverted(&self) -> bool {
+    self.inverted
+  }
+}
+
+fn flash_if_unlocked(mut query: Query<(&mut Portal, &mut Handle<Image>)>) {
+  for (mut portal, mut image) in query.iter_mut() {
+    if portal.unlocked {
+      portal.countdown -= 1;
+      if portal.countdown == 0 {
+        portal.inverted = !portal.inverted;
+        *image = if portal.inverted { portal.inverse_image.clone() } else { portal.normal_image.clone() };
+        portal.countdown = TICKS_PER_FLASH;
       }
     }
   }
 }
 
-fn check_debug_keyboard(keys: Res<Input<KeyCode>>, mut query: Query<&mut Portal>) {
-  if keys.just_released(KeyCode::X) {
+fn check_debug_keyboard(actions: Actions, mut query: Query<&mut Portal>) {
+  if actions.just_released(Action::DebugUnlockPortal) {
     let mut portal = query.get_single_mut().unwrap();
     portal.unlocked = true;
   }
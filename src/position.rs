@@ -16,8 +16,12 @@ pub enum Layer {
   Items = 1,
   Characters = 2,
   Portal = 3,
+  // The whole screen, when it's drawn as a single image.
+  Screen = 4,
   // For HUD etc.
-  Debug = 4,
+  Debug = 5,
+  // Screens shown over the top of the game, like the title screen.
+  Overlay = 6,
 }
 
 /// Represents a position on screen.
@@ -29,13 +33,16 @@ pub enum Layer {
 /// refers to the top leftmost character cell or pixel of the actor.
 ///
 /// The most common way to convert to bevy's coordinate system (and
-/// apply scaling etc) is to convert the position into a Transform.
-#[derive(Component, Debug)]
+/// apply scaling etc) is to convert the position into a Transform. That's
+/// the only place floats are used: everything else works in whole pixels.
+#[derive(Component, Debug, Clone)]
 pub struct Position {
   layer: Layer,
   // The canonical position is the zx spectrum pixel pos, which can
-  // always be snapped to the character cell that it lies within.
-  zx_pixel_pos: (f32, f32),
+  // always be snapped to the character cell that it lies within. It's
+  // signed so that moving off the top or left of the screen can be
+  // noticed rather than wrapping round.
+  zx_pixel_pos: (i16, i16),
 }
 
 
@@ -44,16 +51,25 @@ impl Position {
   pub fn at_char_pos(layer: Layer, pos: (u8, u8)) -> Self {
     let mut me = Position {
       layer,
-      zx_pixel_pos: (0., 0.)
+      zx_pixel_pos: (0, 0)
     };
     me.set_char_pos(pos);
     me
   }
 
+  /// The unscaled zx spectrum pixel position of the top left of this position.
+  pub fn zx_pixel_pos(&self) -> (i16, i16) {
+    self.zx_pixel_pos
+  }
+
+  pub fn set_zx_pixel_pos(&mut self, pos: (i16, i16)) {
+    self.zx_pixel_pos = pos;
+  }
+
   /// Returns true if Willy's head is aligned with the top left of a character
   /// cell boundary (basically, when his y coordinate is divisible by 8)
   pub fn is_vertically_cell_aligned(&self) -> bool {
-    self.zx_pixel_pos.1 % 8. == 0.
+    self.zx_pixel_pos.1.rem_euclid(8) == 0
   }
 
   // Take a single step in the given direction. This moves the
@@ -67,8 +83,8 @@ impl Position {
     let (x, y) = self.zx_pixel_pos;
     self.zx_pixel_pos = (
       x + match direction {
-        Direction::Left => -2.,
-        Direction::Right => 2.,
+        Direction::Left => -2,
+        Direction::Right => 2,
       },
       y,
     );
@@ -81,14 +97,14 @@ impl Position {
       zx_pixel_pos: self.zx_pixel_pos
     };
     temp_position.step_impl(direction);
-    let (cell_x, _) = self.char_pos();
-    let (new_x, _) = temp_position.char_pos();
+    let cell_x = self.zx_pixel_pos.0.div_euclid(8);
+    let new_x = temp_position.zx_pixel_pos.0.div_euclid(8);
 
     new_x != cell_x
   }
 
   // Jump (or fall if distance is negative) the given distance in pixels.
-  pub fn jump(&mut self, distance: f32) {
+  pub fn jump(&mut self, distance: i16) {
     let (x, y) = self.zx_pixel_pos;
     self.zx_pixel_pos = (x, y - distance);
   }
@@ -99,83 +115,90 @@ impl Position {
   /// -(SCALE * 96.0) <= y < (SCALE * 96.0)
   pub fn pixel_pos(&self) -> (f32, f32) {
     let (zx_x, zx_y) = self.zx_pixel_pos;
-    (SCALE * (zx_x - 128.), SCALE * (96. - zx_y))
+    (SCALE * (zx_x as f32 - 128.), SCALE * (96. - zx_y as f32))
   }
 
-  /// Return the character position that contains the pixel.
+  /// Return the character position that contains the pixel, or None if the
+  /// pixel is off the screen.
   /// 0 <= x < 32
   /// 0 <= y < 24
-  pub fn char_pos(&self) -> (u8, u8) {
+  pub fn char_pos(&self) -> Option<(u8, u8)> {
     let (zx_x, zx_y) = self.zx_pixel_pos;
+    let (x, y) = (zx_x.div_euclid(8), zx_y.div_euclid(8));
 
-    ((zx_x / 8.) as u8, (zx_y / 8.) as u8)
+    ((0..32).contains(&x) && (0..24).contains(&y)).then_some((x as u8, y as u8))
   }
 
   pub fn get_cell_box(&self) -> (f32, f32) {
     // Snap x and y back to the start of the current char cell
-    let (char_x, char_y) = self.char_pos();
-    let x = SCALE * (char_x as f32 * 8. - 128.);
-    let y: f32 = SCALE * (96. - (char_y as f32 * 8.));
+    let (zx_x, zx_y) = self.zx_pixel_pos;
+    let x = SCALE * (zx_x.div_euclid(8) as f32 * 8. - 128.);
+    let y: f32 = SCALE * (96. - (zx_y.div_euclid(8) as f32 * 8.));
 
     (x, y)
   }
 
   pub fn set_char_x(&mut self, x: u8) -> &mut Self {
-    self.zx_pixel_pos.0 = x as f32 * 8.;
+    self.zx_pixel_pos.0 = i16::from(x) * 8;
     self
   }
 
   pub fn set_char_y(&mut self, y: u8) {
-    self.zx_pixel_pos.1 = y as f32 * 8.;
+    self.zx_pixel_pos.1 = i16::from(y) * 8;
   }
 
   pub fn set_char_pos(&mut self, (x, y): (u8, u8)) -> &mut Self {
     self.zx_pixel_pos = (
-      x as f32 * 8.,
-      y as f32 * 8.
+      i16::from(x) * 8,
+      i16::from(y) * 8
     );
     self
   }
 
   // Produce a set of positions relative to this one in the given direction. This
-  // yields a (u8, u8) tuple.
+  // yields a (u8, u8) tuple for each cell, leaving out any that lie outside
+  // the cavern, so it's safe to look them all up.
   pub fn relative(&self, relative: Relative) -> Vec<(u8, u8)> {
-    let (x, y) = self.char_pos();
-    let mut positions = Vec::with_capacity(2);
-    match relative {
-      Relative::Below => {
-        positions.push((x, y + 2));
-        positions.push((x + 1, y + 2));
-      },
-      Relative::Inside => {
-        positions.push((x, y));
-        positions.push((x + 1, y));
-        positions.push((x, y + 1));
-        positions.push((x + 1, y + 1));
-      },
-      Relative::Left => {
-        positions.push((x - 1, y));
-        positions.push((x - 1, y + 1));
-      },
-      Relative::Right => {
-        positions.push((x + 2, y));
-        positions.push((x + 2, y + 1));
-      }
-    }
+    let (x, y) = self.zx_pixel_pos;
+    let (x, y) = (x.div_euclid(8), y.div_euclid(8));
+    // Between rows, the sprite overlaps a third row of cells.
+    let straddling = !self.is_vertically_cell_aligned();
+    let offsets: &[(i16, i16)] = match relative {
+      Relative::Below => &[(0, 2), (1, 2)],
+      Relative::Above => &[(0, -1), (1, -1)],
+      Relative::Inside => &[(0, 0), (1, 0), (0, 1), (1, 1)],
+      Relative::Left if straddling => &[(-1, 0), (-1, 1), (-1, 2)],
+      Relative::Left => &[(-1, 0), (-1, 1)],
+      Relative::Right if straddling => &[(2, 0), (2, 1), (2, 2)],
+      Relative::Right => &[(2, 0), (2, 1)],
+    };
 
-    positions
+    offsets
+      .iter()
+      .map(|(dx, dy)| (x + dx, y + dy))
+      .filter(|(x, y)| (0..CAVERN_WIDTH).contains(x) && (0..CAVERN_HEIGHT).contains(y))
+      .map(|(x, y)| (x as u8, y as u8))
+      .collect()
   }
 
 }
 
+/// The size of a cavern, in cells.
+pub const CAVERN_WIDTH: i16 = 32;
+pub const CAVERN_HEIGHT: i16 = 16;
+
 pub enum Relative {
   /// Positions below the current position's cell
   Below,
+  /// Positions above the current position's cell, where a head would hit
+  /// a ceiling
+  Above,
   /// Positions inside the current position's cell
   Inside,
-  /// Positions to the left of the current position's cell
+  /// Positions to the left of the current position's cell, including the
+  /// row below when the position is between rows
   Left,
-  /// Positions to the right
+  /// Positions to the right, likewise
   Right
 }
 
@@ -189,8 +212,7 @@ impl From<&Position> for Transform {
     let z = (value.layer as u32) as f32;
 
     // Snap x back to the start of the current char cell
-    let (char_x, _) = value.char_pos();
-    let x = SCALE * (char_x as f32 * 8. - 128.);
+    let (x, _) = value.get_cell_box();
 
     new_transform().with_translation(Vec3 { x, y, z })
   }
@@ -206,3 +228,43 @@ impl From<Position> for Transform {
 pub fn vec2((x, y): (f32, f32)) -> Vec2 {
   Vec2::new(x, y)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn positions_off_screen_dont_wrap() {
+    let mut position = Position::at_char_pos(Layer::Characters, (0, 1));
+    position.step(Direction::Left);
+    assert_eq!(position.zx_pixel_pos(), (-2, 8));
+    assert_eq!(position.char_pos(), None);
+
+    position.jump(-5);
+    assert!(!position.is_vertically_cell_aligned());
+    position.set_zx_pixel_pos((17, 16));
+    assert!(position.is_vertically_cell_aligned());
+    assert_eq!(position.char_pos(), Some((2, 2)));
+    position.set_zx_pixel_pos((256, 16));
+    assert_eq!(position.char_pos(), None);
+  }
+
+  #[test]
+  fn neighbours_stay_inside_the_cavern() {
+    let position = Position::at_char_pos(Layer::Characters, (0, 0));
+    assert_eq!(position.relative(Relative::Left), vec![]);
+    assert_eq!(position.relative(Relative::Above), vec![]);
+    assert_eq!(position.relative(Relative::Right), vec![(2, 0), (2, 1)]);
+
+    let position = Position::at_char_pos(Layer::Characters, (30, 14));
+    assert_eq!(position.relative(Relative::Below), vec![]);
+    assert_eq!(position.relative(Relative::Right), vec![]);
+    assert_eq!(position.relative(Relative::Above), vec![(30, 13), (31, 13)]);
+    assert_eq!(position.relative(Relative::Inside).len(), 4);
+
+    let mut position = Position::at_char_pos(Layer::Characters, (10, 7));
+    position.jump(-4);
+    assert_eq!(position.relative(Relative::Right), vec![(12, 7), (12, 8), (12, 9)]);
+    assert_eq!(position.relative(Relative::Left), vec![(9, 7), (9, 8), (9, 9)]);
+  }
+}
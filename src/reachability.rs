@@ -0,0 +1,159 @@
+//! Works out which parts of a cavern Willy can reach from his start position,
+//! ignoring guardians. See [crate::simulation] for how Willy is moved.
+
+use crate::{
+  gamedata::cavern::Cavern,
+  position::Relative,
+  simulation::{self, Guardians, Input, Node, Search, WillyState},
+};
+
+/// Something in the cavern that Willy needs to get to, and (if he can) a
+/// sequence of inputs from his start position that gets him there.
+#[derive(Debug)]
+pub struct Target {
+  pub position: (u8, u8),
+  pub route: Option<Vec<Input>>,
+}
+
+impl Target {
+  pub fn is_reachable(&self) -> bool {
+    self.route.is_some()
+  }
+}
+
+/// The result of analysing a cavern.
+#[derive(Debug)]
+pub struct Reachability {
+  cells: [[bool; 16]; 32],
+  pub items: Vec<Target>,
+  pub portal: Target,
+}
+
+impl Reachability {
+  /// Returns true if Willy's sprite can ever cover the given cell.
+  pub fn can_reach(&self, (x, y): (u8, u8)) -> bool {
+    self.cells[x as usize][y as usize]
+  }
+
+  /// Returns true if every item and the portal can be reached. Note that this
+  /// considers each of them separately, from Willy's start position.
+  pub fn is_solvable(&self) -> bool {
+    self.items.iter().all(Target::is_reachable) && self.portal.is_reachable()
+  }
+}
+
+/// Works out everywhere Willy can get to in the given cavern, ignoring guardians.
+pub fn analyse(cavern: &Cavern) -> Reachability {
+  let (search, _) = Search::run(
+    cavern,
+    &Guardians::none(),
+    (WillyState::start(cavern), 0),
+    &[],
+    |_| false,
+    usize::MAX,
+  );
+
+  let mut cells = [[false; 16]; 32];
+  let mut first_item_visit = vec![None; cavern.items.len()];
+  let mut first_portal_visit = None;
+  let portal = simulation::portal_cells(cavern);
+
+  // Nodes are stored in the order they were found, so the first state that
+  // touches each target has the shortest route to it.
+  for (index, Node { state, .. }) in search.nodes.iter().enumerate() {
+    for (x, y) in state.position.relative(Relative::Inside) {
+      cells[x as usize][y as usize] = true;
+    }
+
+    for (item, visit) in cavern.items.iter().zip(first_item_visit.iter_mut()) {
+      if visit.is_none() && state.overlaps(&[item.position]) {
+        *visit = Some(index);
+      }
+    }
+
+    if first_portal_visit.is_none() && state.overlaps(&portal) {
+      first_portal_visit = Some(index);
+    }
+  }
+
+  Reachability {
+    cells,
+    items: cavern
+      .items
+      .iter()
+      .zip(first_item_visit)
+      .map(|(item, visit)| Target {
+        position: item.position,
+        route: visit.map(|i| search.path_to(i).1),
+      })
+      .collect(),
+    portal: Target {
+      position: cavern.portal.position,
+      route: first_portal_visit.map(|i| search.path_to(i).1),
+    },
+  }
+}
+
+/// Finds a sequence of inputs that collects every item and then enters the
+/// portal, ignoring guardians. Gives up after exploring `max_nodes` states.
+pub fn find_route(cavern: &Cavern, max_nodes: usize) -> Option<Vec<Input>> {
+  simulation::plan_route(cavern, &Guardians::none(), max_nodes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::gamedata::GameData;
+  use anyhow::Result;
+
+  #[test]
+  fn central_cavern_is_solvable() -> Result<()> {
+    let game_data = GameData::load("assets/ManicMiner.bin")?;
+    let reachability = analyse(&game_data.caverns[0]);
+
+    assert!(reachability.is_solvable(), "{:?}", reachability);
+    assert!(reachability.can_reach(game_data.caverns[0].willy_start.position));
+
+    Ok(())
+  }
+
+  #[test]
+  fn witness_routes_reach_their_targets() -> Result<()> {
+    let game_data = GameData::load("assets/ManicMiner.bin")?;
+    let cavern = &game_data.caverns[0];
+    let reachability = analyse(cavern);
+
+    for item in reachability.items.iter() {
+      let mut state = WillyState::start(cavern);
+      for input in item.route.as_ref().unwrap() {
+        state = state.step(*input, cavern).unwrap();
+      }
+      assert!(
+        state.overlaps(&[item.position]),
+        "Didn't reach {:?}",
+        item.position
+      );
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn route_collects_items_and_reaches_portal() -> Result<()> {
+    let game_data = GameData::load("assets/ManicMiner.bin")?;
+    let cavern = &game_data.caverns[0];
+    let route = find_route(cavern, usize::MAX).expect("No route found");
+
+    let mut remaining: Vec<_> = cavern.items.iter().map(|i| i.position).collect();
+    let mut state = WillyState::start(cavern);
+    for input in route {
+      state = state.step(input, cavern).unwrap();
+      remaining.retain(|item| !state.overlaps(&[*item]));
+    }
+
+    assert!(remaining.is_empty());
+    assert!(state.overlaps(&simulation::portal_cells(cavern)));
+
+    Ok(())
+  }
+}
@@ -0,0 +1,258 @@
+//! Renders a full ZX Spectrum screen for a cavern into an rgba buffer, without
+//! needing a window. This draws the same things as the game's plugins do when
+//! a cavern starts (tiles, items, guardians, the portal, Willy, the status
+//! area and the border), so it can be used to produce screenshots.
+//!
+//! Caverns can either be drawn the way the game's sprites are, with every
+//! sprite keeping its own colors, or with the attribute clash of the original
+//! hardware (see [crate::clash]).
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::Result;
+use bevy::prelude::Resource;
+
+use crate::{
+  air::{AIR_BAR_GLYPH, AIR_BAR_LENGTH, AIR_BAR_START},
+  bitmap::Bitmap,
+  clash::{self, Scene},
+  color::{Attributes, ColorName},
+  gamedata::GameData,
+  lives::STARTING_LIVES,
+  palette::Palette,
+  scr::Screen,
+  score,
+  text::{text_width, Charset},
+  BORDER_WIDTH_CHARS,
+};
+
+/// The size of the Spectrum's display, in pixels.
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 192;
+
+/// The width of the border drawn around the display, in pixels.
+pub const BORDER_WIDTH: usize = BORDER_WIDTH_CHARS as usize * 8;
+
+/// How caverns are drawn.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+  /// Every sprite is drawn in its own colors, over the top of the cavern.
+  #[default]
+  Sprites,
+  /// Everything is drawn into a single Spectrum screen, so sprites take on the
+  /// colors of the cells they are in, like the original game.
+  Clash,
+}
+
+/// Something that text and bitmaps can be drawn on.
+pub trait Canvas {
+  /// Draws text with its top left at the given character cell.
+  fn draw_text(&mut self, charset: &Charset, text: &str, color: &Attributes, pos: (u8, u8));
+
+  /// Draws a bitmap in the given colors with its top left at the given Spectrum
+  /// pixel position.
+  fn draw_bitmap(&mut self, bitmap: &Bitmap, color: &Attributes, pos: (i32, i32));
+}
+
+/// A screen's worth of rgba pixel data, including the border, along with the
+/// palette it's drawn in.
+pub struct Frame {
+  pub width: usize,
+  pub height: usize,
+  pub rgba: Vec<u8>,
+  pub palette: Palette,
+}
+
+impl Frame {
+  /// Creates a frame filled with the given border color.
+  pub fn new(border_color: &Attributes, palette: &Palette) -> Self {
+    let width = SCREEN_WIDTH + 2 * BORDER_WIDTH;
+    let height = SCREEN_HEIGHT + 2 * BORDER_WIDTH;
+
+    Self {
+      width,
+      height,
+      rgba: border_color.ink_rgba(palette).repeat(width * height),
+      palette: palette.clone(),
+    }
+  }
+
+  /// Copies rgba data `width` pixels wide onto the frame, with its top left at
+  /// the given Spectrum pixel position. Transparent pixels are skipped, as is
+  /// anything that falls outside the frame.
+  pub fn draw_rgba(&mut self, rgba: &[u8], width: usize, (x, y): (i32, i32)) {
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+      if pixel[3] == 0 {
+        continue;
+      }
+
+      let px = x + (i % width) as i32 + BORDER_WIDTH as i32;
+      let py = y + (i / width) as i32 + BORDER_WIDTH as i32;
+      if px < 0 || py < 0 || px as usize >= self.width || py as usize >= self.height {
+        continue;
+      }
+
+      let offset = (py as usize * self.width + px as usize) * 4;
+      self.rgba[offset..offset + 4].copy_from_slice(pixel);
+    }
+  }
+
+  /// Returns the rgba value of the pixel at the given Spectrum pixel position.
+  pub fn pixel(&self, (x, y): (usize, usize)) -> &[u8] {
+    let offset = ((y + BORDER_WIDTH) * self.width + x + BORDER_WIDTH) * 4;
+    &self.rgba[offset..offset + 4]
+  }
+
+  pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    let w = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&self.rgba)?;
+
+    Ok(())
+  }
+}
+
+impl Canvas for Frame {
+  fn draw_text(&mut self, charset: &Charset, text: &str, color: &Attributes, pos: (u8, u8)) {
+    let rgba = charset.to_bitmap(text).render_to_rgba(color, &self.palette);
+    self.draw_rgba(&rgba, text_width(text) * 8, char_to_pixel(pos));
+  }
+
+  fn draw_bitmap(&mut self, bitmap: &Bitmap, color: &Attributes, pos: (i32, i32)) {
+    self.draw_rgba(&bitmap.render_to_rgba(color, &self.palette), bitmap.width(), pos);
+  }
+}
+
+pub fn char_to_pixel((x, y): (u8, u8)) -> (i32, i32) {
+  (x as i32 * 8, y as i32 * 8)
+}
+
+/// Renders the given cavern as it looks when Willy first enters it.
+pub fn render_cavern(
+  game_data: &GameData,
+  charset: &Charset,
+  number: usize,
+  mode: RenderMode,
+  palette: &Palette,
+) -> Frame {
+  let cavern = &game_data.caverns[number];
+  let mut frame = Frame::new(&cavern.border_color, palette);
+
+  if mode == RenderMode::Clash {
+    let mut screen = Screen::blank();
+    clash::compose(&mut screen, &Scene::at_start(game_data, number));
+    draw_status_area(&mut screen, game_data, charset, &cavern.name, &Status::default());
+    frame.draw_rgba(&screen.render_to_rgba(palette), SCREEN_WIDTH, (0, 0));
+
+    return frame;
+  }
+
+  // The cavern itself. Any cell without a matching tile is left black.
+  let black = Attributes::new(ColorName::Black, ColorName::Black, false);
+  for y in 0..16 {
+    for x in 0..32 {
+      match cavern.get_bg_sprite_index((x, y)) {
+        Some(index) => {
+          let tile = &cavern.tile_bitmaps[index];
+          frame.draw_bitmap(tile, tile.color.as_ref().unwrap(), char_to_pixel((x, y)));
+        }
+        None => frame.draw_text(charset, " ", &black, (x, y)),
+      }
+    }
+  }
+
+  for item in cavern.items.iter() {
+    frame.draw_bitmap(&cavern.item_bitmap, &item.attributes, char_to_pixel(item.position));
+  }
+
+  let portal = &cavern.portal;
+  frame.draw_bitmap(&portal.bitmap, &portal.attributes, char_to_pixel(portal.position));
+
+  let scene = Scene::at_start(game_data, number);
+  for (bitmap, color, pos) in scene.guardians.iter() {
+    frame.draw_bitmap(bitmap, color, *pos);
+  }
+
+  let (willy, pos) = scene.willy;
+  frame.draw_bitmap(willy, &Attributes::new_transparent_bg(ColorName::White, false), pos);
+
+  draw_status_area(&mut frame, game_data, charset, &cavern.name, &Status::default());
+
+  frame
+}
+
+/// The scores and lives shown below the cavern.
+pub struct Status {
+  pub score: u16,
+  pub high_score: u16,
+  pub lives: u8,
+}
+
+impl Default for Status {
+  /// How things stand at the start of a game.
+  fn default() -> Self {
+    Status { score: 0, high_score: 0, lives: STARTING_LIVES }
+  }
+}
+
+/// Draws everything below the cavern: its name, the air supply, scores and
+/// the remaining lives.
+pub fn draw_status_area(frame: &mut impl Canvas, game_data: &GameData, charset: &Charset, name: &str, status: &Status) {
+  let yellow = Attributes::new(ColorName::Yellow, ColorName::Black, false);
+  let black = Attributes::new(ColorName::Black, ColorName::Black, false);
+
+  frame.draw_text(charset, name, &Attributes::new(ColorName::Black, ColorName::Yellow, false), (0, 16));
+  let red = Attributes::new(ColorName::White, ColorName::Red, true);
+  let green = Attributes::new(ColorName::White, ColorName::Green, true);
+  frame.draw_text(charset, "AIR       ", &red, (0, 17));
+  frame.draw_text(charset, &" ".repeat(22), &green, (10, 17));
+  let bar = Bitmap::create(8, 8, &AIR_BAR_GLYPH);
+  for x in AIR_BAR_START..AIR_BAR_START + AIR_BAR_LENGTH as u8 {
+    frame.draw_bitmap(&bar, if x < 10 { &red } else { &green }, char_to_pixel((x, 17)));
+  }
+  frame.draw_text(charset, &" ".repeat(32), &black, (0, 18));
+  frame.draw_text(
+    charset,
+    &format!("High Score {}   Score {}", score::pad(status.high_score), score::pad(status.score)),
+    &yellow,
+    (0, 19),
+  );
+
+  for y in 20..24 {
+    frame.draw_text(charset, &" ".repeat(32), &black, (0, y));
+  }
+
+  // One life is the one being played, so isn't shown.
+  let life_color = Attributes::new(ColorName::Cyan, ColorName::Black, true);
+  for i in 0..status.lives.saturating_sub(1) {
+    frame.draw_bitmap(&game_data.willy_sprites[0], &life_color, char_to_pixel((i * 2, 21)));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{gamedata::GAME_DATA_PATH, text::CHARSET_PATH};
+
+  #[test]
+  fn renders_full_screen_with_border() -> Result<()> {
+    let game_data = GameData::load(GAME_DATA_PATH)?;
+    let charset = Charset::load(CHARSET_PATH)?;
+    let palette = Palette::default();
+    let frame = render_cavern(&game_data, &charset, 0, RenderMode::Sprites, &palette);
+
+    assert_eq!((frame.width, frame.height), (320, 256));
+    assert_eq!(frame.rgba.len(), 320 * 256 * 4);
+
+    // The top left corner is border, and the cavern name's paper is yellow.
+    assert_eq!(frame.rgba[0..4], game_data.caverns[0].border_color.ink_rgba(&palette));
+    let offset = ((BORDER_WIDTH + 16 * 8) * frame.width + BORDER_WIDTH) * 4;
+    assert_eq!(frame.rgba[offset..offset + 4], [0xee, 0xee, 0x00, 0xff]);
+
+    Ok(())
+  }
+}
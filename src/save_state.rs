@@ -0,0 +1,436 @@
+//! Quick saving and loading. The whole state of the game is written to a slot
+//! file in a line based text format, e.g.
+//!
+//! ```text
+//! minerwilly save 2
+//! cavern 3
+//! score 120 1000
+//! lives 2
+//! clock 244
+//! willy 16 104 walking 1 jumping 3
+//! guardian 64 56 walking 2
+//! collected 5 7
+//! crumble 10 5 3
+//! crumble 11 5 gone
+//! ```
+//!
+//! Guardians are listed in the order the cavern lists them. The first line
+//! holds the version of the format, and saves from other versions are
+//! refused.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bevy::prelude::*;
+
+use crate::{
+  actions::{Action, Actions, ReadInput},
+  actors::{HorizontalMotion, FRAME_COUNT},
+  cavern::{CavernState, CurrentCavern},
+  gamedata::{GameDataResource, CAVERN_COUNT},
+  guardian::{GameClock, Guardian},
+  handle_errors,
+  item::Item,
+  lives::Lives,
+  position::{Position, CAVERN_HEIGHT, CAVERN_WIDTH},
+  score::Score,
+  willy::{self, AirborneStatus, Willy, MAX_JUMP_COUNTER},
+  GameState,
+};
+
+/// Where quick saves are kept.
+pub const SAVE_PATH: &str = "minerwilly.sav";
+
+/// The version of the save format, which changes whenever the format does.
+const SAVE_VERSION: u32 = 2;
+
+pub struct SaveStatePlugin;
+
+impl Plugin for SaveStatePlugin {
+  fn build(&self, app: &mut App) {
+    // Loading happens before `Update`, so that the cavern is respawned by the
+    // time the rest of the save is applied.
+    app.add_systems(
+      PreUpdate,
+      check_keyboard.pipe(handle_errors).after(ReadInput).run_if(in_state(GameState::Playing)),
+    );
+    app.add_systems(PostUpdate, apply_load.run_if(resource_exists::<PendingLoad>()));
+  }
+}
+
+/// Where an actor is and how it's moving.
+#[derive(Debug, Clone, PartialEq)]
+struct ActorState {
+  zx_pixel_pos: (i16, i16),
+  walking: bool,
+  current_frame: usize,
+}
+
+impl ActorState {
+  fn new(position: &Position, motion: &HorizontalMotion) -> Self {
+    ActorState {
+      zx_pixel_pos: position.zx_pixel_pos(),
+      walking: motion.walking,
+      current_frame: motion.current_frame,
+    }
+  }
+
+  fn apply(&self, position: &mut Position, motion: &mut HorizontalMotion) {
+    position.set_zx_pixel_pos(self.zx_pixel_pos);
+    motion.walking = self.walking;
+    motion.current_frame = self.current_frame;
+  }
+}
+
+impl std::fmt::Display for ActorState {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let (x, y) = self.zx_pixel_pos;
+    let walking = if self.walking { "walking" } else { "standing" };
+    write!(f, "{} {} {} {}", x, y, walking, self.current_frame)
+  }
+}
+
+/// Everything needed to carry on a game from where it was saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+  cavern: usize,
+  score: u16,
+  high_score: u16,
+  lives: u8,
+  clock: u8,
+  willy: ActorState,
+  airborne_status: AirborneStatus,
+  jump_counter: u8,
+  guardians: Vec<ActorState>,
+  collected: Vec<(u8, u8)>,
+  crumbled_floors: Vec<((u8, u8), Option<u8>)>,
+}
+
+impl SaveState {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    SaveState::try_from(text.as_str())
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    std::fs::write(path, self.to_string())?;
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for SaveState {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "minerwilly save {}", SAVE_VERSION)?;
+    writeln!(f, "cavern {}", self.cavern)?;
+    writeln!(f, "score {} {}", self.score, self.high_score)?;
+    writeln!(f, "lives {}", self.lives)?;
+    writeln!(f, "clock {}", self.clock)?;
+    writeln!(f, "willy {} {} {}", self.willy, self.airborne_status.name(), self.jump_counter)?;
+    for guardian in &self.guardians {
+      writeln!(f, "guardian {}", guardian)?;
+    }
+    for (x, y) in &self.collected {
+      writeln!(f, "collected {} {}", x, y)?;
+    }
+    for ((x, y), level) in &self.crumbled_floors {
+      match level {
+        Some(level) => writeln!(f, "crumble {} {} {}", x, y, level)?,
+        None => writeln!(f, "crumble {} {} gone", x, y)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+impl TryFrom<&str> for SaveState {
+  type Error = anyhow::Error;
+
+  fn try_from(s: &str) -> Result<Self> {
+    let mut lines = s.lines().filter(|line| !line.trim().is_empty());
+
+    let version: u32 = lines
+      .next()
+      .and_then(|line| line.strip_prefix("minerwilly save "))
+      .context("Not a save state")?
+      .trim()
+      .parse()?;
+    anyhow::ensure!(
+      version == SAVE_VERSION,
+      "Save states from version {} can't be loaded (expected version {})",
+      version,
+      SAVE_VERSION
+    );
+
+    let mut state = SaveState {
+      cavern: 0,
+      score: 0,
+      high_score: 0,
+      lives: 0,
+      clock: 0,
+      willy: ActorState { zx_pixel_pos: (0, 0), walking: false, current_frame: 0 },
+      airborne_status: AirborneStatus::NotJumpingOrFalling,
+      jump_counter: 0,
+      guardians: Vec::new(),
+      collected: Vec::new(),
+      crumbled_floors: Vec::new(),
+    };
+
+    for line in lines {
+      parse_line(&mut state, line).with_context(|| format!("Bad line in save state: {}", line))?;
+    }
+
+    Ok(state)
+  }
+}
+
+fn parse_line(state: &mut SaveState, line: &str) -> Result<()> {
+  let mut fields = line.split_whitespace();
+  let mut next = || fields.next().context("Missing value");
+
+  match next()? {
+    "cavern" => {
+      state.cavern = next()?.parse()?;
+      anyhow::ensure!(state.cavern < CAVERN_COUNT, "There's no cavern {}", state.cavern);
+    }
+    "score" => {
+      state.score = next()?.parse()?;
+      state.high_score = next()?.parse()?;
+    }
+    "lives" => state.lives = next()?.parse()?,
+    "clock" => state.clock = next()?.parse()?,
+    "willy" => {
+      state.willy = parse_actor(&mut next)?;
+      let status = next()?;
+      state.airborne_status = *AirborneStatus::ALL
+        .iter()
+        .find(|s| s.name() == status)
+        .with_context(|| format!("Unknown airborne status: {}", status))?;
+      state.jump_counter = next()?.parse()?;
+      anyhow::ensure!(
+        state.jump_counter <= MAX_JUMP_COUNTER,
+        "Jump counters go from 0 to {}, not {}",
+        MAX_JUMP_COUNTER,
+        state.jump_counter
+      );
+    }
+    "guardian" => state.guardians.push(parse_actor(&mut next)?),
+    "collected" => state.collected.push(parse_cell(&mut next)?),
+    "crumble" => {
+      let position = parse_cell(&mut next)?;
+      let level = match next()? {
+        "gone" => None,
+        level => Some(level.parse()?),
+      };
+      if let Some(level) = level {
+        anyhow::ensure!(level <= 7, "Crumble levels go from 0 to 7, not {}", level);
+      }
+      state.crumbled_floors.push((position, level));
+    }
+    name => anyhow::bail!("Unknown setting: {}", name),
+  }
+
+  Ok(())
+}
+
+/// Parses the position of a cell, which must be inside the cavern.
+fn parse_cell<'a>(next: &mut impl FnMut() -> Result<&'a str>) -> Result<(u8, u8)> {
+  let (x, y): (u8, u8) = (next()?.parse()?, next()?.parse()?);
+  anyhow::ensure!(
+    (x as i16) < CAVERN_WIDTH && (y as i16) < CAVERN_HEIGHT,
+    "{} {} is outside the cavern",
+    x,
+    y
+  );
+
+  Ok((x, y))
+}
+
+/// Parses where an actor is and how it's moving. It must be inside the
+/// cavern, and on one of its animation frames.
+fn parse_actor<'a>(next: &mut impl FnMut() -> Result<&'a str>) -> Result<ActorState> {
+  let (x, y): (i16, i16) = (next()?.parse()?, next()?.parse()?);
+  anyhow::ensure!(
+    (0..CAVERN_WIDTH * 8).contains(&x) && (0..CAVERN_HEIGHT * 8).contains(&y),
+    "Pixel {} {} is outside the cavern",
+    x,
+    y
+  );
+  let walking = match next()? {
+    "walking" => true,
+    "standing" => false,
+    other => anyhow::bail!("Expected walking or standing, not {}", other),
+  };
+  let current_frame = next()?.parse()?;
+  anyhow::ensure!(
+    current_frame < FRAME_COUNT,
+    "Animation frames go from 0 to {}, not {}",
+    FRAME_COUNT - 1,
+    current_frame
+  );
+
+  Ok(ActorState { zx_pixel_pos: (x, y), walking, current_frame })
+}
+
+/// A save state that's being loaded, once its cavern has been set up.
+#[derive(Resource)]
+struct PendingLoad(SaveState);
+
+#[allow(clippy::too_many_arguments)]
+fn check_keyboard(
+  mut commands: Commands,
+  actions: Actions,
+  mut cavern: ResMut<CurrentCavern>,
+  cavern_state: Res<CavernState>,
+  score: Res<Score>,
+  lives: Res<Lives>,
+  clock: Res<GameClock>,
+  willy: Query<(&Willy, &Position, &HorizontalMotion)>,
+  guardians: Query<(&Guardian, &Position, &HorizontalMotion)>,
+  items: Query<(&Item, &Position)>,
+) -> Result<()> {
+  if actions.just_pressed(Action::QuickSave) {
+    let (w, position, motion) = willy.get_single()?;
+    let mut sorted: Vec<_> = guardians.iter().collect();
+    sorted.sort_by_key(|(guardian, _, _)| guardian.id);
+
+    SaveState {
+      cavern: cavern.number,
+      score: score.score,
+      high_score: score.high_score,
+      lives: lives.lives_remaining,
+      clock: clock.0,
+      willy: ActorState::new(position, motion),
+      airborne_status: w.airborne_status,
+      jump_counter: w.jump_counter(),
+      guardians: sorted.into_iter().map(|(_, p, m)| ActorState::new(p, m)).collect(),
+      collected: items.iter().filter(|(item, _)| item.collected).filter_map(|(_, p)| p.char_pos()).collect(),
+      crumbled_floors: cavern_state.crumbled_floors(),
+    }
+    .save(SAVE_PATH)?;
+    info!("Saved to {}", SAVE_PATH);
+  } else if actions.just_pressed(Action::QuickLoad) {
+    let state = SaveState::load(SAVE_PATH)?;
+    // Start the cavern afresh, even if it's the one being played, and fill in
+    // the rest once it's been set up.
+    cavern.number = state.cavern;
+    cavern.set_changed();
+    commands.insert_resource(PendingLoad(state));
+    info!("Loaded {}", SAVE_PATH);
+  }
+
+  Ok(())
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_load(
+  mut commands: Commands,
+  pending: Res<PendingLoad>,
+  game_data: Res<GameDataResource>,
+  cavern: Res<CurrentCavern>,
+  mut cavern_state: ResMut<CavernState>,
+  mut score: ResMut<Score>,
+  mut lives: ResMut<Lives>,
+  mut clock: ResMut<GameClock>,
+  mut willy: Query<(&mut Willy, &mut Position, &mut HorizontalMotion), (Without<Guardian>, Without<Item>)>,
+  mut guardians: Query<(&Guardian, &mut Position, &mut HorizontalMotion), Without<Willy>>,
+  mut items: Query<(&mut Item, &Position), (Without<Willy>, Without<Guardian>)>,
+) {
+  let state = &pending.0;
+  commands.remove_resource::<PendingLoad>();
+
+  score.score = state.score;
+  score.high_score = state.high_score;
+  lives.lives_remaining = state.lives;
+  *clock = GameClock(state.clock);
+
+  for (position, level) in &state.crumbled_floors {
+    cavern_state.set_crumbled_floor(*position, *level);
+  }
+
+  if let Ok((mut w, mut position, mut motion)) = willy.get_single_mut() {
+    w.set_airborne_status(state.airborne_status, state.jump_counter);
+    state.willy.apply(&mut position, &mut motion);
+    // The flags were worked out where the cavern started him, not where he is now.
+    willy::update_wall_flags(&mut w, &position, &cavern_state);
+    willy::update_conveyor_flags(&mut w, &position, &game_data.caverns[cavern.number].conveyor);
+  }
+  for (guardian, mut position, mut motion) in guardians.iter_mut() {
+    if let Some(saved) = state.guardians.get(guardian.id as usize) {
+      saved.apply(&mut position, &mut motion);
+    }
+  }
+  for (mut item, position) in items.iter_mut() {
+    if position.char_pos().is_some_and(|cell| state.collected.contains(&cell)) {
+      item.collected = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn save_states_round_trip_through_text() -> Result<()> {
+    let text = "minerwilly save 2\n\
+      cavern 3\n\
+      score 120 1000\n\
+      lives 2\n\
+      clock 244\n\
+      willy 16 104 walking 1 jumping 3\n\
+      guardian 64 56 walking 2\n\
+      guardian 70 80 standing 5\n\
+      collected 5 7\n\
+      crumble 10 5 3\n\
+      crumble 11 5 gone\n";
+
+    let state = SaveState::try_from(text)?;
+    assert_eq!(state.cavern, 3);
+    assert_eq!(state.airborne_status, AirborneStatus::Jumping);
+    assert_eq!(state.guardians[1], ActorState { zx_pixel_pos: (70, 80), walking: false, current_frame: 5 });
+    assert_eq!(state.crumbled_floors, vec![((10, 5), Some(3)), ((11, 5), None)]);
+    assert_eq!(state.to_string(), text);
+
+    Ok(())
+  }
+
+  #[test]
+  fn other_versions_are_refused() {
+    assert!(SaveState::try_from("minerwilly save 1\ncavern 3\n").is_err());
+    assert!(SaveState::try_from("cavern 3\n").is_err());
+    assert!(SaveState::try_from("minerwilly save 2\nwilly 16 104 walking 1 flying 3\n").is_err());
+  }
+
+  #[test]
+  fn saves_that_dont_fit_the_game_are_refused() {
+    assert!(SaveState::try_from("minerwilly save 2\ncavern 19\n").is_ok());
+    assert!(SaveState::try_from("minerwilly save 2\ncavern 40\n").is_err());
+    assert!(SaveState::try_from("minerwilly save 2\ncrumble 40 3 2\n").is_err());
+    assert!(SaveState::try_from("minerwilly save 2\ncrumble 3 16 2\n").is_err());
+    assert!(SaveState::try_from("minerwilly save 2\ncrumble 3 3 9\n").is_err());
+    assert!(SaveState::try_from("minerwilly save 2\ncollected 32 0\n").is_err());
+  }
+
+  #[test]
+  fn actors_that_dont_fit_the_game_are_refused() {
+    let willy = |fields: &str| SaveState::try_from(format!("minerwilly save 2\nwilly {}\n", fields).as_str());
+    let guardian = |fields: &str| SaveState::try_from(format!("minerwilly save 2\nguardian {}\n", fields).as_str());
+
+    assert!(willy("16 104 walking 7 falling 48").is_ok());
+    assert!(guardian("248 120 standing 7").is_ok());
+
+    // Animation frames past the last sprite.
+    assert!(willy("16 104 walking 8 standing 0").is_err());
+    assert!(guardian("64 56 walking 8").is_err());
+
+    // Pixels outside the 256x128 playfield.
+    assert!(willy("-1 104 walking 1 standing 0").is_err());
+    assert!(willy("16 128 walking 1 standing 0").is_err());
+    assert!(guardian("256 56 walking 2").is_err());
+    assert!(guardian("64 -8 walking 2").is_err());
+
+    // Jump counters past the end of the longest fall.
+    assert!(willy("16 104 walking 1 falling 49").is_err());
+  }
+}
@@ -1,6 +1,7 @@
+use crate::actions::{Action, Actions};
 use crate::color::ColorName;
 use crate::{
-  handle_errors,
+  handle_errors, GameState,
   text::{Text, TextAttributes},
 };
 use anyhow::Result;
@@ -21,6 +22,12 @@ impl Score {
     // In the original game, this doesn't update until the game is over
     //    self.high_score = Ord::max(self.score, self.high_score);
   }
+
+  /// Ends the game, keeping the score if it's a new high score.
+  pub fn reset(&mut self) {
+    self.high_score = Ord::max(self.score, self.high_score);
+    self.score = 0;
+  }
 }
 
 #[derive(Component)]
@@ -35,7 +42,7 @@ impl Plugin for ScorePlugin {
     app.add_systems(
       Update,
       (
-        check_debug_keyboard,
+        check_debug_keyboard.run_if(in_state(GameState::Playing)),
         update_score.pipe(handle_errors),
         update_high_score.pipe(handle_errors),
       ),
@@ -76,12 +83,13 @@ fn update_high_score(
   Ok(())
 }
 
-fn pad(score: u16) -> String {
+/// Formats a score the way it is shown on screen, as six digits.
+pub fn pad(score: u16) -> String {
   format!("{:0>6}", score)
 }
 
-fn check_debug_keyboard(keys: Res<Input<KeyCode>>, mut score: ResMut<Score>) {
-  if keys.just_released(KeyCode::Return) {
+fn check_debug_keyboard(actions: Actions, mut score: ResMut<Score>) {
+  if actions.just_released(Action::DebugAddScore) {
     score.add(100);
   }
 }
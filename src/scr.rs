@@ -0,0 +1,269 @@
+//! Reads and writes ZX Spectrum `.scr` screen files. These are a straight
+//! dump of the Spectrum's display file: 6144 bytes of bitmap data, with the
+//! rows interleaved the way the hardware lays them out in memory, followed by
+//! 768 bytes of attributes, one per character cell.
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+
+use crate::{
+  bitmap::{self, Bitmap},
+  color::{Attributes, ColorName},
+  palette::Palette,
+  render::{char_to_pixel, Canvas, Frame, SCREEN_HEIGHT, SCREEN_WIDTH},
+  text::{charcode, Charset},
+};
+
+const BITMAP_SIZE_BYTES: usize = SCREEN_WIDTH / 8 * SCREEN_HEIGHT;
+const ATTRIBUTES_SIZE_BYTES: usize = 32 * 24;
+
+/// The size of a `.scr` file.
+pub const SCR_SIZE_BYTES: usize = BITMAP_SIZE_BYTES + ATTRIBUTES_SIZE_BYTES;
+
+/// A full Spectrum screen: a 256x192 bitmap and a 32x24 grid of attributes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+  pub bitmap: Bitmap,
+  pub attributes: Vec<Attributes>,
+}
+
+impl Screen {
+  /// A screen with no pixels set, and black ink and paper everywhere.
+  pub fn blank() -> Self {
+    Self {
+      bitmap: Bitmap::create(SCREEN_WIDTH, SCREEN_HEIGHT, &[0; BITMAP_SIZE_BYTES]),
+      attributes: vec![Attributes::default(); ATTRIBUTES_SIZE_BYTES],
+    }
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    Screen::try_from(fs::read(path)?.as_slice())
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    fs::write(path, Vec::from(self))?;
+    Ok(())
+  }
+
+  pub fn get_attributes(&self, (char_x, char_y): (usize, usize)) -> &Attributes {
+    &self.attributes[char_y * 32 + char_x]
+  }
+
+  pub fn set_attributes(&mut self, (char_x, char_y): (usize, usize), color: Attributes) {
+    if char_x < 32 && char_y < 24 {
+      // The screen has no notion of transparency.
+      self.attributes[char_y * 32 + char_x] = Attributes {
+        transparent_background: false,
+        ..color
+      };
+    }
+  }
+
+  /// Copies a bitmap onto the screen with its top left at the given pixel
+  /// position, replacing whatever was there.
+  pub fn copy_bitmap(&mut self, bitmap: &Bitmap, pos: (i32, i32)) {
+    self.draw_pixels(bitmap, pos, |_, new| new);
+  }
+
+  /// Draws a bitmap onto the screen with its top left at the given pixel
+  /// position, leaving any pixels that were already set.
+  pub fn or_bitmap(&mut self, bitmap: &Bitmap, pos: (i32, i32)) {
+    self.draw_pixels(bitmap, pos, |old, new| old || new);
+  }
+
+  fn draw_pixels(&mut self, bitmap: &Bitmap, (x, y): (i32, i32), blend: impl Fn(bool, bool) -> bool) {
+    for by in 0..bitmap.height() {
+      for bx in 0..bitmap.width() {
+        let (sx, sy) = (x + bx as i32, y + by as i32);
+        if sx < 0 || sy < 0 || sx as usize >= SCREEN_WIDTH || sy as usize >= SCREEN_HEIGHT {
+          continue;
+        }
+
+        let pos = (sx as usize, sy as usize);
+        let value = blend(self.bitmap.get_pixel(pos), bitmap.get_pixel((bx, by)));
+        self.bitmap.set_pixel(pos, value);
+      }
+    }
+  }
+
+  /// Renders the screen as rgba pixel data, as the Spectrum would display it
+  /// (ignoring flashing).
+  pub fn render_to_rgba(&self, palette: &Palette) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+
+    for (y, row) in self.bitmap.data().chunks_exact(SCREEN_WIDTH / 8).enumerate() {
+      for (char_x, byte) in row.iter().enumerate() {
+        let color = self.get_attributes((char_x, y / 8));
+        bitmap::to_rgba(&mut rgba, byte, &color.ink_rgba(palette), &color.paper_rgba(palette));
+      }
+    }
+
+    rgba
+  }
+}
+
+/// Returns the character cells on screen that a bitmap drawn with its top left
+/// at the given pixel position would cover.
+pub fn cells_covered(bitmap: &Bitmap, (x, y): (i32, i32)) -> Vec<(usize, usize)> {
+  let columns = x.max(0) as usize / 8..=(x + bitmap.width() as i32 - 1).max(0) as usize / 8;
+  let rows = y.max(0) as usize / 8..=(y + bitmap.height() as i32 - 1).max(0) as usize / 8;
+
+  rows
+    .flat_map(|row| columns.clone().map(move |column| (column, row)))
+    .filter(|(column, row)| *column < 32 && *row < 24)
+    .collect()
+}
+
+impl Canvas for Screen {
+  fn draw_text(&mut self, charset: &Charset, text: &str, color: &Attributes, (x, y): (u8, u8)) {
+    for (i, c) in text.chars().enumerate() {
+      let pos = (x + i as u8, y);
+      self.set_attributes((pos.0 as usize, pos.1 as usize), *color);
+      self.copy_bitmap(&Bitmap::create(8, 8, charset.glyph(charcode(c))), char_to_pixel(pos));
+    }
+  }
+
+  fn draw_bitmap(&mut self, bitmap: &Bitmap, color: &Attributes, pos: (i32, i32)) {
+    for cell in cells_covered(bitmap, pos) {
+      self.set_attributes(cell, *color);
+    }
+    self.or_bitmap(bitmap, pos);
+  }
+}
+
+/// Returns the offset into the display file of the first byte of the given
+/// pixel row. The screen is split into thirds of 64 rows, and within each third
+/// the first pixel row of every character row comes first, then the second
+/// pixel row of each, and so on.
+fn row_offset(y: usize) -> usize {
+  ((y & 0b11000000) << 5) | ((y & 0b111) << 8) | ((y & 0b111000) << 2)
+}
+
+impl TryFrom<&[u8]> for Screen {
+  type Error = anyhow::Error;
+
+  fn try_from(bytes: &[u8]) -> Result<Screen> {
+    anyhow::ensure!(bytes.len() == SCR_SIZE_BYTES, "Expected {} bytes", SCR_SIZE_BYTES);
+
+    let mut data = Vec::with_capacity(BITMAP_SIZE_BYTES);
+    for y in 0..SCREEN_HEIGHT {
+      let offset = row_offset(y);
+      data.extend_from_slice(&bytes[offset..offset + SCREEN_WIDTH / 8]);
+    }
+
+    Ok(Screen {
+      bitmap: Bitmap::create(SCREEN_WIDTH, SCREEN_HEIGHT, &data),
+      attributes: bytes[BITMAP_SIZE_BYTES..].iter().map(|b| Attributes::from(*b)).collect(),
+    })
+  }
+}
+
+impl From<&Screen> for Vec<u8> {
+  fn from(screen: &Screen) -> Vec<u8> {
+    let mut bytes = vec![0; SCR_SIZE_BYTES];
+
+    for (y, row) in screen.bitmap.data().chunks_exact(SCREEN_WIDTH / 8).enumerate() {
+      let offset = row_offset(y);
+      bytes[offset..offset + row.len()].copy_from_slice(row);
+    }
+
+    for (byte, color) in bytes[BITMAP_SIZE_BYTES..].iter_mut().zip(screen.attributes.iter()) {
+      *byte = color.into();
+    }
+
+    bytes
+  }
+}
+
+/// Converts a rendered frame into a screen. Each character cell can only have
+/// two colors, so the most common color in each cell becomes its paper, the
+/// next most common becomes its ink, and any other colors are drawn as ink.
+/// Colors that aren't in the frame's palette are treated as black.
+impl From<&Frame> for Screen {
+  fn from(frame: &Frame) -> Screen {
+    let mut screen = Screen::blank();
+
+    for char_y in 0..SCREEN_HEIGHT / 8 {
+      for char_x in 0..SCREEN_WIDTH / 8 {
+        let pixels: Vec<_> = (0..64)
+          .map(|i| (char_x * 8 + i % 8, char_y * 8 + i / 8))
+          .map(|pos| (pos, frame.palette.find(frame.pixel(pos)).unwrap_or((0, false))))
+          .collect();
+
+        let mut counts: Vec<((u8, bool), usize)> = vec![];
+        for (_, color) in pixels.iter() {
+          match counts.iter_mut().find(|(c, _)| c == color) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((*color, 1)),
+          }
+        }
+        counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        let paper = counts[0].0;
+        let ink = counts.get(1).map_or(paper, |(c, _)| *c);
+
+        for (pos, color) in pixels {
+          screen.bitmap.set_pixel(pos, color != paper);
+        }
+        screen.attributes[char_y * 32 + char_x] = Attributes {
+          // Black looks the same whether it's bright or not.
+          bright: paper.1 || ink.1,
+          ..Attributes::new(ColorName::from(ink.0), ColorName::from(paper.0), false)
+        };
+      }
+    }
+
+    screen
+  }
+}
+
+/// Frames the screen with a black border, using the default palette.
+impl From<&Screen> for Frame {
+  fn from(screen: &Screen) -> Frame {
+    let palette = Palette::default();
+    let mut frame = Frame::new(&Attributes::default(), &palette);
+    frame.draw_rgba(&screen.render_to_rgba(&palette), SCREEN_WIDTH, (0, 0));
+
+    frame
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rows_are_interleaved() -> Result<()> {
+    let mut bytes = vec![0; SCR_SIZE_BYTES];
+    // The second pixel row starts 256 bytes in, the ninth (the first row of
+    // the second character row) 32 bytes in, and the second third 2048 bytes in.
+    bytes[256] = 0b10000000;
+    bytes[32] = 0b01000000;
+    bytes[2048] = 0b00100000;
+    bytes[BITMAP_SIZE_BYTES + 33] = 0b11001110;
+
+    let screen = Screen::try_from(bytes.as_slice())?;
+
+    assert!(screen.bitmap.get_pixel((0, 1)));
+    assert!(screen.bitmap.get_pixel((1, 8)));
+    assert!(screen.bitmap.get_pixel((2, 64)));
+    assert_eq!(screen.bitmap.data().iter().map(|b| b.count_ones()).sum::<u32>(), 3);
+    assert_eq!(u8::from(screen.get_attributes((1, 1))), 0b11001110);
+
+    assert_eq!(Vec::from(&screen), bytes);
+
+    Ok(())
+  }
+
+  #[test]
+  fn frames_convert_to_screens_and_back() -> Result<()> {
+    let mut screen = Screen::blank();
+    screen.bitmap.set_pixel((10, 20), true);
+    screen.attributes[2 * 32 + 1] = Attributes::new(ColorName::Yellow, ColorName::Blue, true);
+
+    assert_eq!(Screen::from(&Frame::from(&screen)), screen);
+
+    Ok(())
+  }
+}
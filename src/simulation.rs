@@ -0,0 +1,609 @@
+//! A headless simulation of a cavern, for working out how to play it.
+//!
+//! Willy and the guardians are moved with the same code that moves them in the
+//! game (see [willy::tick] and [guardian::advance]), so the answers always
+//! match how the game actually plays. On top of that, this provides a breadth
+//! first search over every sequence of inputs, which is used both to analyse
+//! caverns and to play through them.
+
+use std::{
+  cell::Cell,
+  collections::{HashSet, VecDeque},
+  rc::Rc,
+};
+
+use anyhow::Result;
+
+use crate::{
+  actors::HorizontalMotion,
+  cavern::CavernState,
+  gamedata::cavern::{Cavern, CavernTileType},
+  guardian::{self, GameClock},
+  position::{Position, Relative},
+  willy::{self, AirborneStatus, KeyboardState, Willy},
+};
+
+/// Once Willy has been falling for this many ticks, falling any further
+/// doesn't change how he moves, so all such states are treated as the same.
+const MAX_JUMP_COUNTER: u8 = 21;
+
+/// The most states a single search will explore before giving up. This keeps
+/// memory use reasonable (each state takes around 100 bytes).
+const MAX_SEARCH_NODES: usize = 4_000_000;
+
+/// The longest we'll simulate guardians for when looking for the point where
+/// they all return to where they started.
+const MAX_GUARDIAN_TICKS: usize = 2048;
+
+/// The input held down during a single game tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+  Idle,
+  Left,
+  Right,
+  Jump,
+  JumpLeft,
+  JumpRight,
+}
+
+impl Input {
+  pub const ALL: [Input; 6] = [
+    Input::Idle,
+    Input::Left,
+    Input::Right,
+    Input::Jump,
+    Input::JumpLeft,
+    Input::JumpRight,
+  ];
+
+  /// The keyboard state that corresponds to this input.
+  pub fn keys(&self) -> KeyboardState {
+    KeyboardState {
+      left_pressed: matches!(self, Input::Left | Input::JumpLeft),
+      right_pressed: matches!(self, Input::Right | Input::JumpRight),
+      jump_pressed: matches!(self, Input::Jump | Input::JumpLeft | Input::JumpRight),
+    }
+  }
+}
+
+impl std::fmt::Display for Input {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      Input::Idle => "-",
+      Input::Left => "L",
+      Input::Right => "R",
+      Input::Jump => "J",
+      Input::JumpLeft => "JL",
+      Input::JumpRight => "JR",
+    })
+  }
+}
+
+impl TryFrom<&str> for Input {
+  type Error = anyhow::Error;
+
+  fn try_from(s: &str) -> Result<Self> {
+    Input::ALL
+      .into_iter()
+      .find(|input| input.to_string() == s)
+      .ok_or_else(|| anyhow::anyhow!("Unknown input `{}`", s))
+  }
+}
+
+/// Describes a route compactly, collapsing repeated inputs. E.g. `R*12 JR -*3`.
+pub fn describe_route(route: &[Input]) -> String {
+  let mut parts: Vec<String> = vec![];
+  let mut i = 0;
+  while i < route.len() {
+    let count = route[i..]
+      .iter()
+      .take_while(|input| **input == route[i])
+      .count();
+    parts.push(if count == 1 {
+      route[i].to_string()
+    } else {
+      format!("{}*{}", route[i], count)
+    });
+    i += count;
+  }
+  parts.join(" ")
+}
+
+/// Parses a route in the format produced by [describe_route].
+pub fn parse_route(description: &str) -> Result<Vec<Input>> {
+  let mut route = vec![];
+  for part in description.split_whitespace() {
+    let (input, count) = match part.split_once('*') {
+      Some((input, count)) => (input, count.parse()?),
+      None => (part, 1),
+    };
+    route.extend(std::iter::repeat_n(Input::try_from(input)?, count));
+  }
+  Ok(route)
+}
+
+/// Everything about Willy that affects how he moves from one tick to the next.
+#[derive(Debug, Clone)]
+pub struct WillyState {
+  pub willy: Willy,
+  pub motion: HorizontalMotion,
+  pub position: Position,
+  // The cavern as Willy has left it, with whatever floors he's crumbled. It's
+  // shared between states until he crumbles some more.
+  cavern_state: Rc<CavernState>,
+}
+
+// States are told apart by how far the floor under Willy has crumbled, but not
+// by what's happened to the other crumbling floors, as tracking all of them
+// makes the search far too big. So the search can miss a route that needs a
+// floor to still be there. Any route it does find is exact, though, as each
+// state is stepped with the floors as Willy actually left them.
+type StateKey = (i16, i16, AirborneStatus, u8, usize, bool, Vec<u8>);
+
+impl WillyState {
+  /// Willy's state at the start of the given cavern.
+  pub fn start(cavern: &Cavern) -> Self {
+    let (willy, position, motion) = willy::start_state(cavern);
+
+    WillyState {
+      willy,
+      motion,
+      position,
+      cavern_state: Rc::new(CavernState::from(cavern)),
+    }
+  }
+
+  /// Returns true if any of the cells covered by Willy's sprite are in `cells`.
+  pub fn overlaps(&self, cells: &[(u8, u8)]) -> bool {
+    self
+      .position
+      .relative(Relative::Inside)
+      .iter()
+      .any(|c| cells.contains(c))
+  }
+
+  /// Advances Willy by one tick with the given input held down. Returns None if
+  /// Willy died, or left the playfield.
+  pub fn step(&self, input: Input, cavern: &Cavern) -> Option<WillyState> {
+    if !in_bounds(&self.position) {
+      return None;
+    }
+
+    let mut next = self.clone();
+
+    // Floors crumble before Willy moves, as in the game.
+    let on_crumbling_floor =
+      self.cavern_state.is_type(&self.position, Relative::Below, CavernTileType::CrumblingFloor);
+    if !self.willy.airborne_status.is_airborne() && on_crumbling_floor {
+      Rc::make_mut(&mut next.cavern_state).crumble_under(&self.position);
+    }
+    let cavern_state = &*next.cavern_state;
+
+    willy::tick(
+      &mut next.willy,
+      &mut next.motion,
+      &mut next.position,
+      &input.keys(),
+      cavern_state,
+      &cavern.conveyor,
+    );
+
+    // Landing after falling too far is fatal.
+    let was_unsafe = self.willy.airborne_status == AirborneStatus::FallingUnsafeToLand;
+    if was_unsafe && !next.willy.airborne_status.is_airborne() {
+      return None;
+    }
+
+    if next
+      .position
+      .relative(Relative::Inside)
+      .iter()
+      .any(|c| cavern_state.get_tile_type(*c).is_nasty())
+    {
+      return None;
+    }
+
+    Some(next)
+  }
+
+  fn key(&self) -> StateKey {
+    let (x, y) = self.position.zx_pixel_pos();
+    (
+      x,
+      y,
+      self.willy.airborne_status,
+      self.willy.jump_counter().min(MAX_JUMP_COUNTER),
+      self.motion.current_frame,
+      self.motion.walking,
+      self
+        .position
+        .relative(Relative::Below)
+        .into_iter()
+        .map(|cell| self.cavern_state.get_crumble_level(cell))
+        .collect(),
+    )
+  }
+}
+
+/// Returns true if all the cells that Willy's movement code looks at during
+/// the next tick lie inside the cavern. He can fall up to 4 pixels in a tick,
+/// and mustn't go below row 13, because the cells below him are checked.
+fn in_bounds(position: &Position) -> bool {
+  let (x, y) = position.zx_pixel_pos();
+  (1..=29).contains(&x.div_euclid(8)) && y + 4 < 14 * 8
+}
+
+/// The cells covered by the guardians on each tick. Guardians move the same
+/// way regardless of what Willy does, so this is worked out up front.
+pub struct Guardians {
+  cells: Vec<Vec<(u8, u8)>>,
+  // True if the guardians return to their start positions after `cells.len()`
+  // ticks. If not, we only know where they are for that many ticks.
+  periodic: bool,
+}
+
+impl Guardians {
+  /// No guardians at all.
+  pub fn none() -> Self {
+    Guardians {
+      cells: vec![vec![]],
+      periodic: true,
+    }
+  }
+
+  fn phase(&self, tick: usize) -> usize {
+    if self.periodic {
+      tick % self.cells.len()
+    } else {
+      tick
+    }
+  }
+
+  /// Returns true if we know where the guardians are at the given tick.
+  fn is_known(&self, tick: usize) -> bool {
+    self.periodic || tick < self.cells.len()
+  }
+
+  /// Returns true if Willy would be touching a guardian at the given tick.
+  pub fn collides(&self, willy: &WillyState, tick: usize) -> bool {
+    willy.overlaps(&self.cells[self.phase(tick)])
+  }
+}
+
+impl From<&Cavern> for Guardians {
+  fn from(cavern: &Cavern) -> Self {
+    let mut guardians: Vec<_> = cavern
+      .guardians
+      .iter()
+      .map(guardian::start_position)
+      .collect();
+    let start: Vec<_> = guardians
+      .iter()
+      .map(|(p, m)| (p.zx_pixel_pos(), m.current_frame))
+      .collect();
+    // Slow guardians only move on every other tick, so they're only back
+    // where they started if the clock is too.
+    let mut clock = GameClock(cavern.clock);
+
+    let mut cells = vec![];
+    let mut periodic = false;
+    while cells.len() < MAX_GUARDIAN_TICKS {
+      cells.push(
+        guardians
+          .iter()
+          .flat_map(|(p, _)| p.relative(Relative::Inside))
+          .collect(),
+      );
+
+      for ((position, motion), data) in guardians.iter_mut().zip(cavern.guardians.iter()) {
+        guardian::advance(motion, position, data, clock);
+      }
+      clock.tick();
+
+      if (clock.0 ^ cavern.clock) & 4 == 0 && guardians
+        .iter()
+        .map(|(p, m)| (p.zx_pixel_pos(), m.current_frame))
+        .eq(start.iter().copied())
+      {
+        periodic = true;
+        break;
+      }
+    }
+
+    Guardians { cells, periodic }
+  }
+}
+
+/// A state reached during a search: where Willy is and on which tick, whether
+/// he has touched the target on his way there, and the node and input that led
+/// to it.
+pub struct Node {
+  pub state: WillyState,
+  pub tick: usize,
+  pub touched: bool,
+  parent: Option<(usize, Input)>,
+}
+
+/// A breadth first search over Willy's states. Every state reached is kept,
+/// so that the shortest route to any of them can be recovered.
+pub struct Search {
+  pub nodes: Vec<Node>,
+}
+
+impl Search {
+  /// Searches outwards from `start` on the given tick, tracking whether Willy
+  /// has touched any of the `target` cells along the way, until `is_goal`
+  /// returns true for a node. Returns the index of that node, if there is one.
+  /// The search gives up once it has found `max_nodes` states.
+  pub fn run(
+    cavern: &Cavern,
+    guardians: &Guardians,
+    (start, start_tick): (WillyState, usize),
+    target: &[(u8, u8)],
+    is_goal: impl Fn(&Node) -> bool,
+    max_nodes: usize,
+  ) -> (Search, Option<usize>) {
+    let mut search = Search { nodes: vec![] };
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    let touched = start.overlaps(target);
+    seen.insert((start.key(), guardians.phase(start_tick), touched));
+    search.nodes.push(Node {
+      state: start,
+      tick: start_tick,
+      touched,
+      parent: None,
+    });
+    queue.push_back(0);
+
+    while let Some(index) = queue.pop_front() {
+      if is_goal(&search.nodes[index]) {
+        return (search, Some(index));
+      }
+
+      let tick = search.nodes[index].tick + 1;
+      if !guardians.is_known(tick) {
+        continue;
+      }
+      if search.nodes.len() >= max_nodes {
+        break;
+      }
+
+      for input in Input::ALL {
+        let Some(next) = search.nodes[index].state.step(input, cavern) else {
+          continue;
+        };
+        if guardians.collides(&next, tick) {
+          continue;
+        }
+
+        let touched = search.nodes[index].touched || next.overlaps(target);
+        if seen.insert((next.key(), guardians.phase(tick), touched)) {
+          queue.push_back(search.nodes.len());
+          search.nodes.push(Node {
+            state: next,
+            tick,
+            touched,
+            parent: Some((index, input)),
+          });
+        }
+      }
+    }
+
+    (search, None)
+  }
+
+  /// The nodes passed through on the way from the start of the search to the
+  /// given node, and the inputs that lead from each to the next.
+  pub fn path_to(&self, mut index: usize) -> (Vec<usize>, Vec<Input>) {
+    let mut path = vec![index];
+    let mut route = vec![];
+    while let Some((parent, input)) = self.nodes[index].parent {
+      path.push(parent);
+      route.push(input);
+      index = parent;
+    }
+    path.reverse();
+    route.reverse();
+    (path, route)
+  }
+}
+
+/// The cells covered by the portal in the given cavern.
+pub fn portal_cells(cavern: &Cavern) -> Vec<(u8, u8)> {
+  let (x, y) = cavern.portal.position;
+  vec![(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+}
+
+/// Finds a sequence of inputs that collects every item and then enters the
+/// portal, avoiding the given guardians. Items are visited nearest first,
+/// backtracking whenever that leads to a dead end, so the route found isn't
+/// necessarily the shortest. Gives up if it can't find a route after exploring
+/// `max_nodes` states in total.
+pub fn plan_route(cavern: &Cavern, guardians: &Guardians, max_nodes: usize) -> Option<Vec<Input>> {
+  let planner = Planner {
+    cavern,
+    guardians,
+    nodes_remaining: Cell::new(max_nodes),
+  };
+  let items: Vec<(u8, u8)> = cavern.items.iter().map(|i| i.position).collect();
+
+  planner.route_from((WillyState::start(cavern), 0), &items)
+}
+
+struct Planner<'a> {
+  cavern: &'a Cavern,
+  guardians: &'a Guardians,
+  nodes_remaining: Cell<usize>,
+}
+
+impl Planner<'_> {
+  fn search(
+    &self,
+    start: (WillyState, usize),
+    target: &[(u8, u8)],
+    is_goal: impl Fn(&Node) -> bool,
+  ) -> (Search, Option<usize>) {
+    let max_nodes = self.nodes_remaining.get().min(MAX_SEARCH_NODES);
+    let (search, goal) = Search::run(
+      self.cavern,
+      self.guardians,
+      start,
+      target,
+      is_goal,
+      max_nodes,
+    );
+    self
+      .nodes_remaining
+      .set(self.nodes_remaining.get() - search.nodes.len());
+
+    (search, goal)
+  }
+
+  fn route_from(&self, start: (WillyState, usize), remaining: &[(u8, u8)]) -> Option<Vec<Input>> {
+    if self.nodes_remaining.get() == 0 {
+      return None;
+    }
+
+    if remaining.is_empty() {
+      let portal = portal_cells(self.cavern);
+      let (search, goal) = self.search(start, &portal, |n| n.touched);
+      return goal.map(|g| search.path_to(g).1);
+    }
+
+    // First head for whichever item is nearest, and only if that leads to a dead
+    // end try going for each of the other items first.
+    let nearest = self.leg(start.clone(), remaining);
+    let tried = nearest
+      .as_ref()
+      .map(|(path, _, search)| search.nodes[*path.last().unwrap()].state.key());
+    let others = remaining.iter().filter_map(|item| {
+      self
+        .leg(start.clone(), &[*item])
+        .filter(|(path, _, search)| tried != Some(search.nodes[*path.last().unwrap()].state.key()))
+    });
+
+    for (path, route, search) in nearest.into_iter().chain(others) {
+      // Any other items passed on the way are collected too.
+      let still_remaining: Vec<(u8, u8)> = remaining
+        .iter()
+        .filter(|item| {
+          !path
+            .iter()
+            .any(|i| search.nodes[*i].state.overlaps(&[**item]))
+        })
+        .copied()
+        .collect();
+      let end = &search.nodes[*path.last().unwrap()];
+
+      if let Some(rest) = self.route_from((end.state.clone(), end.tick), &still_remaining) {
+        let mut route = route;
+        route.extend(rest);
+        return Some(route);
+      }
+    }
+
+    None
+  }
+
+  /// Finds the quickest way to touch any of the given items and then land
+  /// safely. Landing matters, as otherwise we could pick a route that collects
+  /// an item on the way to certain death.
+  fn leg(
+    &self,
+    start: (WillyState, usize),
+    items: &[(u8, u8)],
+  ) -> Option<(Vec<usize>, Vec<Input>, Search)> {
+    let (search, goal) = self.search(start, items, |n| {
+      n.touched && !n.state.willy.airborne_status.is_airborne()
+    });
+    let (path, route) = search.path_to(goal?);
+
+    Some((path, route, search))
+  }
+}
+
+/// What happened when a sequence of inputs was played.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+  /// Willy collected every item and entered the portal.
+  Completed { ticks: usize },
+  /// Willy died (or left the cavern) on the given tick.
+  Died { tick: usize },
+  /// The inputs ran out before the cavern was completed.
+  Unfinished { items_remaining: usize },
+}
+
+/// Plays the given inputs through the cavern, one per tick, and reports what
+/// happened. Guardians are moved alongside Willy, rather than relying on
+/// [Guardians], so this can be used to check routes independently.
+pub fn play(cavern: &Cavern, inputs: &[Input]) -> Outcome {
+  let portal = portal_cells(cavern);
+  let mut remaining: Vec<(u8, u8)> = cavern.items.iter().map(|i| i.position).collect();
+  let mut state = WillyState::start(cavern);
+  let mut guardians: Vec<_> = cavern
+    .guardians
+    .iter()
+    .map(guardian::start_position)
+    .collect();
+  let mut clock = GameClock(cavern.clock);
+
+  for (tick, input) in (1..).zip(inputs) {
+    state = match state.step(*input, cavern) {
+      Some(next) => next,
+      None => return Outcome::Died { tick },
+    };
+
+    for ((position, motion), data) in guardians.iter_mut().zip(cavern.guardians.iter()) {
+      guardian::advance(motion, position, data, clock);
+    }
+    clock.tick();
+    let guardian_cells: Vec<_> = guardians
+      .iter()
+      .flat_map(|(p, _)| p.relative(Relative::Inside))
+      .collect();
+    if state.overlaps(&guardian_cells) {
+      return Outcome::Died { tick };
+    }
+
+    remaining.retain(|item| !state.overlaps(&[*item]));
+    if remaining.is_empty() && state.overlaps(&portal) {
+      return Outcome::Completed { ticks: tick };
+    }
+  }
+
+  Outcome::Unfinished {
+    items_remaining: remaining.len(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn routes_round_trip_through_descriptions() -> Result<()> {
+    let route = vec![
+      Input::Right,
+      Input::Right,
+      Input::JumpRight,
+      Input::Idle,
+      Input::Idle,
+      Input::Idle,
+      Input::Left,
+    ];
+
+    let description = describe_route(&route);
+    assert_eq!(description, "R*2 JR -*3 L");
+    assert_eq!(parse_route(&description)?, route);
+
+    Ok(())
+  }
+
+  #[test]
+  fn bad_routes_dont_parse() {
+    assert!(parse_route("R*2 X").is_err());
+    assert!(parse_route("R*two").is_err());
+  }
+}
@@ -1,23 +1,110 @@
 use crate::{
+  bitmap::Bitmap,
   color::{Attributes, ColorName},
+  palette::Palette,
   position::{Layer, Position},
 };
 use anyhow::Result;
-use bevy::{
-  prelude::*,
-  render::render_resource::{Extent3d, TextureDimension, TextureFormat},
-  sprite::Anchor,
-};
+use bevy::{prelude::*, sprite::Anchor};
 use std::io::Read;
+use std::ops::Range;
 use std::{fs::File, path::Path};
 
 use crate::handle_errors;
 
+/// The file that the character set is loaded from.
+pub const CHARSET_PATH: &str = "assets/charset.bin";
+
+/// The first character code in the character set, which is a space.
+const FIRST_CODE: u8 = 0x20;
+
+/// The first of the user-defined graphics, which follow the ROM's characters
+/// and the block graphics.
+const FIRST_UDG: u8 = 0x90;
+
+/// The number of user-defined graphics, which are typed as A-U on the Spectrum.
+pub const UDG_COUNT: usize = 21;
+
+/// The number of characters in the character set, including block graphics
+/// and user-defined graphics.
+const GLYPH_COUNT: usize = (FIRST_UDG - FIRST_CODE) as usize + UDG_COUNT;
+
+/// The unicode characters that stand for the Spectrum's block graphics,
+/// 0x80-0x8f, in order. The first has no pixels set, so it's a space.
+const BLOCK_GRAPHICS: [char; 16] = [
+  ' ', '▝', '▘', '▀', '▗', '▐', '▚', '▜', '▖', '▞', '▌', '▛', '▄', '▟', '▙', '█',
+];
+
+/// User-defined graphics are written in text as the characters in Unicode's
+/// private use area at this offset from their character code.
+const UDG_CHAR_OFFSET: u32 = 0xe000;
+
+/// Returns the character that stands for the given user-defined graphic (0-20)
+/// in text.
+pub fn udg(n: usize) -> char {
+  assert!(n < UDG_COUNT, "No such UDG: {}", n);
+  char::from_u32(UDG_CHAR_OFFSET + FIRST_UDG as u32 + n as u32).unwrap()
+}
+
+/// Returns the Spectrum character code for the given character. Printable
+/// ascii maps to itself, as on the Spectrum, so `^` is an up arrow and `` ` ``
+/// is a pound sign. Characters that the Spectrum has no equivalent for are
+/// treated as spaces.
+pub fn charcode(c: char) -> u8 {
+  let udgs = FIRST_UDG as u32..FIRST_UDG as u32 + UDG_COUNT as u32;
+
+  match c {
+    '£' => 0x60,
+    '©' => 0x7f,
+    '↑' => 0x5e,
+    ' '..='~' => c as u8,
+    _ if udgs.contains(&(c as u32).wrapping_sub(UDG_CHAR_OFFSET)) => {
+      (c as u32 - UDG_CHAR_OFFSET) as u8
+    }
+    _ => BLOCK_GRAPHICS
+      .iter()
+      .position(|b| *b == c)
+      .map_or(FIRST_CODE, |i| 0x80 + i as u8),
+  }
+}
+
+/// Returns the width of the given text in characters.
+pub fn text_width(text: &str) -> usize {
+  text.chars().count()
+}
+
+/// How the lines of wrapped text are placed within their box.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Alignment {
+  #[default]
+  Left,
+  Centre,
+  Right,
+}
+
+/// How the characters of a [Text] are laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+  /// Everything on a single line.
+  Line,
+  /// Wrapped at spaces (or explicit newlines) to fit in a box of the given
+  /// width and height in characters. Any lines that don't fit are dropped.
+  Wrapped { size: (u8, u8), alignment: Alignment },
+  /// A single line of the given width in characters, which the text scrolls
+  /// through from right to left, starting `offset` characters in. The text
+  /// wraps around once it reaches the end.
+  Marquee { width: u8, offset: usize },
+}
+
 #[derive(Component, Debug)]
 pub struct Text {
   pub value: String,
   pub pos: (u8, u8),
   pub attributes: TextAttributes,
+  /// Ranges of characters that are drawn with their own attributes, rather
+  /// than `attributes`. Later spans take priority.
+  spans: Vec<(Range<usize>, TextAttributes)>,
+  layout: Layout,
   sprite_entity: Option<Entity>,
   layer: Layer,
 }
@@ -28,6 +115,8 @@ impl Text {
       value: value.to_owned(),
       pos,
       attributes: *attributes,
+      spans: vec![],
+      layout: Layout::Line,
       sprite_entity: None,
       layer: Layer::Tiles,
     }
@@ -43,20 +132,139 @@ impl Text {
       value: value.to_owned(),
       pos,
       attributes: *attributes,
+      spans: vec![],
+      layout: Layout::Line,
       sprite_entity: None,
       layer,
     }
   }
+
+  /// Draws the characters in the given range with their own attributes.
+  pub fn with_span(mut self, range: Range<usize>, attributes: &TextAttributes) -> Self {
+    self.spans.push((range, *attributes));
+    self
+  }
+
+  /// Wraps the text to fit in a box of the given width and height in
+  /// characters, with its top left at the text's position.
+  pub fn wrapped(mut self, size: (u8, u8), alignment: Alignment) -> Self {
+    self.layout = Layout::Wrapped { size, alignment };
+    self
+  }
+
+  /// Scrolls the text through a single line of the given width in characters.
+  pub fn marquee(mut self, width: u8) -> Self {
+    self.layout = Layout::Marquee { width, offset: 0 };
+    self
+  }
+
+  /// The attributes the character at the given index is drawn with.
+  pub fn attributes_at(&self, index: usize) -> &TextAttributes {
+    self
+      .spans
+      .iter()
+      .rev()
+      .find(|(range, _)| range.contains(&index))
+      .map_or(&self.attributes, |(_, attributes)| attributes)
+  }
+
+  fn has_flashing_attributes(&self) -> bool {
+    self.attributes.flash || self.spans.iter().any(|(_, attributes)| attributes.flash)
+  }
+
+  /// Returns the index of each character that's shown, along with the cell
+  /// it's drawn in relative to the text's position.
+  pub fn cells(&self) -> Vec<(usize, (u8, u8))> {
+    let chars: Vec<char> = self.value.chars().collect();
+
+    match self.layout {
+      Layout::Line => (0..chars.len()).map(|i| (i, (i as u8, 0))).collect(),
+      Layout::Wrapped { size: (width, height), alignment } => wrap(&chars, width as usize)
+        .into_iter()
+        .take(height as usize)
+        .enumerate()
+        .flat_map(|(y, line)| {
+          let space = (width as usize).saturating_sub(line.len());
+          let indent = match alignment {
+            Alignment::Left => 0,
+            Alignment::Centre => space / 2,
+            Alignment::Right => space,
+          };
+          line.into_iter().enumerate().map(move |(x, i)| (i, ((indent + x) as u8, y as u8)))
+        })
+        .collect(),
+      Layout::Marquee { width, offset } if !chars.is_empty() => {
+        (0..width).map(|x| ((offset + x as usize) % chars.len(), (x, 0))).collect()
+      }
+      Layout::Marquee { .. } => vec![],
+    }
+  }
+
+  /// Moves marquee text along by one character.
+  fn scroll(&mut self) {
+    if let Layout::Marquee { offset, .. } = &mut self.layout {
+      *offset = (*offset + 1) % text_width(&self.value).max(1);
+    }
+  }
 }
 
-#[derive(Resource, Deref)]
-struct CharsetResource(Charset);
+/// Splits text into lines of at most `width` characters, breaking at spaces
+/// and newlines where possible, and returns the index of each character on
+/// each line. Spaces where lines are broken are dropped, as are runs of more
+/// than one space.
+fn wrap(chars: &[char], width: usize) -> Vec<Vec<usize>> {
+  let mut lines = vec![];
+
+  for paragraph in split_indices(0..chars.len(), |i| chars[i] == '\n') {
+    let mut line: Vec<usize> = vec![];
+    for word in split_indices(paragraph, |i| chars[i] == ' ') {
+      for chunk in word.chunks(width.max(1)) {
+        if !line.is_empty() && line.len() + 1 + chunk.len() > width {
+          lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+          // The space before this word.
+          line.push(chunk[0] - 1);
+        }
+        line.extend_from_slice(chunk);
+      }
+    }
+    lines.push(line);
+  }
+
+  lines
+}
 
-#[derive(Default, Debug, Copy, Clone)]
+/// Splits the given indices wherever `is_separator` is true, dropping the
+/// separators.
+fn split_indices(
+  indices: impl IntoIterator<Item = usize>,
+  is_separator: impl Fn(usize) -> bool,
+) -> Vec<Vec<usize>> {
+  let mut groups = vec![vec![]];
+  for i in indices {
+    if is_separator(i) {
+      groups.push(vec![]);
+    } else {
+      groups.last_mut().unwrap().push(i);
+    }
+  }
+
+  groups
+}
+
+/// The character set that text is drawn with. User-defined graphics can be
+/// changed at any time, and text is redrawn with them.
+#[derive(Resource, Deref, DerefMut)]
+pub struct CharsetResource(pub Charset);
+
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 pub struct TextAttributes {
   ink: ColorName,
   paper: ColorName,
   bright: bool,
+  /// Swaps the ink and paper every 16 frames, like the Spectrum's FLASH.
+  flash: bool,
 }
 
 impl TextAttributes {
@@ -64,7 +272,7 @@ impl TextAttributes {
     Self {
       ink,
       paper,
-      bright: false,
+      ..Default::default()
     }
   }
 
@@ -73,124 +281,359 @@ impl TextAttributes {
       ink,
       paper,
       bright: true,
+      ..Default::default()
     }
   }
-}
 
-fn create_text(charset: &Charset, text: &str, attributes: &TextAttributes) -> Image {
-  charset.to_image(
-    &Attributes::new(attributes.ink, attributes.paper, attributes.bright),
-    text,
-  )
+  /// These attributes with FLASH set.
+  pub fn flashing(self) -> Self {
+    Self { flash: true, ..self }
+  }
 }
 
-fn tile_sprite(layer: Layer, texture: Handle<Image>, pos: (u8, u8)) -> SpriteBundle {
-  SpriteBundle {
-    sprite: new_top_left_sprite(),
-    texture,
-    transform: Position::at_char_pos(layer, pos).into(),
-    ..default()
-  }
+/// The width and height of a character, in pixels.
+const GLYPH_SIZE: f32 = 8.;
+
+/// Every glyph in the character set, drawn as white ink on a transparent
+/// background so that sprites can tint it to any ink color.
+#[derive(Resource)]
+struct GlyphAtlas {
+  atlas: Handle<TextureAtlas>,
+  texture: Handle<Image>,
 }
 
-fn new_top_left_sprite() -> Sprite {
-  Sprite {
-    anchor: Anchor::TopLeft,
-    ..default()
-  }
+/// How long the Spectrum takes to swap the ink and paper of flashing
+/// characters: 16 frames at 50 frames a second.
+const FLASH_SECONDS: f32 = 16. / 50.;
+
+/// How long marquee text waits before moving along a character.
+const MARQUEE_SECONDS: f32 = 8. / 50.;
+
+/// Whether flashing text currently has its ink and paper swapped.
+#[derive(Resource)]
+struct Flash {
+  timer: Timer,
+  inverted: bool,
 }
 
+#[derive(Resource, Deref, DerefMut)]
+struct MarqueeTimer(Timer);
+
+/// The parent of the sprites that the given [Text] entity is drawn with.
+#[derive(Component)]
+struct TextSprites(Entity);
+
 pub struct TextPlugin;
 
 impl Plugin for TextPlugin {
   fn build(&self, app: &mut App) {
     app.add_systems(Startup, load_charset.pipe(handle_errors));
-    app.add_systems(Update, render_text);
+    app.insert_resource(Flash {
+      timer: Timer::from_seconds(FLASH_SECONDS, TimerMode::Repeating),
+      inverted: false,
+    });
+    app.insert_resource(MarqueeTimer(Timer::from_seconds(MARQUEE_SECONDS, TimerMode::Repeating)));
+    app.add_systems(
+      Update,
+      (
+        update_atlas,
+        recolor_text,
+        flash_text,
+        scroll_marquees,
+        render_text,
+        despawn_orphaned_sprites,
+      )
+        .chain(),
+    );
   }
 }
 
+/// How a single character of a [Text] is drawn, relative to the text.
+struct Cell {
+  offset: Vec3,
+  glyph: usize,
+  ink: Color,
+  paper: Color,
+}
+
+impl Cell {
+  /// The cells that the given text is currently drawn as.
+  fn all(text: &Text, palette: &Palette, flash: &Flash) -> Vec<Cell> {
+    let chars: Vec<char> = text.value.chars().collect();
+
+    text
+      .cells()
+      .into_iter()
+      .map(|(i, (x, y))| {
+        let mut color = Attributes::from(text.attributes_at(i));
+        if color.flash && flash.inverted {
+          color = color.inverse();
+        }
+
+        Cell {
+          offset: Vec3::new(x as f32 * GLYPH_SIZE, -(y as f32) * GLYPH_SIZE, 0.),
+          glyph: glyph_index(charcode(chars[i])),
+          ink: color.ink_color(palette),
+          paper: color.paper_color(palette),
+        }
+      })
+      .collect()
+  }
+
+  /// Spawns a square of the paper color, with the glyph on top of it.
+  fn spawn(&self, parent: &mut ChildBuilder, atlas: &GlyphAtlas) {
+    parent
+      .spawn(SpriteBundle {
+        sprite: Sprite {
+          color: self.paper,
+          custom_size: Some(Vec2::splat(GLYPH_SIZE)),
+          anchor: Anchor::TopLeft,
+          ..default()
+        },
+        transform: Transform::from_translation(self.offset),
+        ..default()
+      })
+      .with_children(|paper| {
+        paper.spawn(SpriteSheetBundle {
+          sprite: TextureAtlasSprite {
+            index: self.glyph,
+            color: self.ink,
+            anchor: Anchor::TopLeft,
+            ..default()
+          },
+          texture_atlas: atlas.atlas.clone(),
+          transform: Transform::from_xyz(0., 0., 0.1),
+          ..default()
+        });
+      });
+  }
+}
+
+/// Draws each character as its own sprite, on top of a square of its paper
+/// color, all under a single parent entity. When text changes, the sprites
+/// it already has are updated in place, and sprites are only spawned or
+/// despawned if the number of characters shown changes.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn render_text(
   mut commands: Commands,
-  charset: Res<CharsetResource>,
-  mut images: ResMut<Assets<Image>>,
-  mut query: Query<&mut Text, Changed<Text>>,
+  atlas: Res<GlyphAtlas>,
+  palette: Res<Palette>,
+  flash: Res<Flash>,
+  mut query: Query<(Entity, &mut Text), Changed<Text>>,
+  mut parents: Query<(&mut Transform, Option<&Children>), With<TextSprites>>,
+  mut papers: Query<(&mut Sprite, &mut Transform, &Children), Without<TextSprites>>,
+  mut glyphs: Query<&mut TextureAtlasSprite>,
 ) {
-  query.for_each_mut(|mut text| {
-    // Despawn any previous instance of the text.
-    if let Some(entity) = text.sprite_entity {
-      commands.entity(entity).despawn();
-      text.sprite_entity = None;
-    }
-
-    let image_handle = images.add(create_text(&charset, &text.value, &text.attributes));
-    let id = commands
-      .spawn(tile_sprite(text.layer, image_handle, text.pos))
-      .id();
-    text.sprite_entity = Some(id);
+  query.for_each_mut(|(owner, mut text)| {
+    let transform = Position::at_char_pos(text.layer, text.pos).into();
+    let cells = Cell::all(&text, &palette, &flash);
+
+    let (parent, existing) = match text.sprite_entity.map(|e| (e, parents.get_mut(e))) {
+      Some((parent, Ok((mut parent_transform, children)))) => {
+        *parent_transform = transform;
+        (parent, children.map_or(vec![], |c| c.to_vec()))
+      }
+      _ => {
+        let id = commands.spawn((TextSprites(owner), SpatialBundle::from_transform(transform))).id();
+        text.sprite_entity = Some(id);
+        (id, vec![])
+      }
+    };
+
+    for (cell, entity) in cells.iter().zip(&existing) {
+      let Ok((mut sprite, mut cell_transform, children)) = papers.get_mut(*entity) else {
+        continue;
+      };
+      sprite.color = cell.paper;
+      cell_transform.translation = cell.offset;
+      for child in children {
+        if let Ok(mut glyph) = glyphs.get_mut(*child) {
+          glyph.index = cell.glyph;
+          glyph.color = cell.ink;
+        }
+      }
+    }
+
+    if cells.len() > existing.len() {
+      commands.entity(parent).with_children(|parent| {
+        for cell in &cells[existing.len()..] {
+          cell.spawn(parent, &atlas);
+        }
+      });
+    }
+    for entity in existing.iter().skip(cells.len()) {
+      commands.entity(*entity).despawn_recursive();
+    }
   });
 }
 
-fn load_charset(mut commands: Commands) -> Result<()> {
-  commands.insert_resource(CharsetResource(Charset::load("assets/charset.bin")?));
+/// Despawns the sprites of any text that has been despawned.
+fn despawn_orphaned_sprites(
+  mut commands: Commands,
+  mut removed: RemovedComponents<Text>,
+  query: Query<(Entity, &TextSprites)>,
+) {
+  let removed: Vec<Entity> = removed.iter().collect();
+  if removed.is_empty() {
+    return;
+  }
+
+  for (entity, sprites) in query.iter() {
+    if removed.contains(&sprites.0) {
+      commands.entity(entity).despawn_recursive();
+    }
+  }
+}
+
+/// Redraws the atlas when the character set changes, which happens when
+/// user-defined graphics are changed.
+fn update_atlas(charset: Res<CharsetResource>, atlas: Res<GlyphAtlas>, mut images: ResMut<Assets<Image>>) {
+  if charset.is_changed() {
+    if let Some(image) = images.get_mut(&atlas.texture) {
+      *image = charset.to_atlas_bitmap().render_mask();
+    }
+  }
+}
+
+/// Redraws all text in the new palette when it changes.
+fn recolor_text(palette: Res<Palette>, mut query: Query<&mut Text>) {
+  if palette.is_changed() && !palette.is_added() {
+    for mut text in query.iter_mut() {
+      text.set_changed();
+    }
+  }
+}
+
+/// Swaps the ink and paper of flashing text.
+fn flash_text(time: Res<Time>, mut flash: ResMut<Flash>, mut query: Query<&mut Text>) {
+  if flash.timer.tick(time.delta()).just_finished() {
+    flash.inverted = !flash.inverted;
+    for mut text in query.iter_mut() {
+      if text.has_flashing_attributes() {
+        text.set_changed();
+      }
+    }
+  }
+}
+
+fn scroll_marquees(time: Res<Time>, mut timer: ResMut<MarqueeTimer>, mut query: Query<&mut Text>) {
+  if timer.tick(time.delta()).just_finished() {
+    for mut text in query.iter_mut() {
+      if matches!(text.layout, Layout::Marquee { .. }) {
+        text.scroll();
+      }
+    }
+  }
+}
+
+fn load_charset(
+  mut commands: Commands,
+  mut images: ResMut<Assets<Image>>,
+  mut atlases: ResMut<Assets<TextureAtlas>>,
+) -> Result<()> {
+  let charset = Charset::load(CHARSET_PATH)?;
+
+  let texture = images.add(charset.to_atlas_bitmap().render_mask());
+  let atlas = TextureAtlas::from_grid(texture.clone(), Vec2::splat(GLYPH_SIZE), GLYPH_COUNT, 1, None, None);
+  commands.insert_resource(GlyphAtlas {
+    atlas: atlases.add(atlas),
+    texture,
+  });
+  commands.insert_resource(CharsetResource(charset));
 
   Ok(())
 }
 
-struct Charset {
+/// The ZX Spectrum character set: the 96 characters from the ROM (0x20-0x7f),
+/// the 16 block graphics (0x80-0x8f) and the 21 user-defined graphics
+/// (0x90-0xa4).
+pub struct Charset {
   bytes: Vec<u8>,
 }
 
 impl Charset {
+  /// Loads the ROM's characters. The user-defined graphics start off as copies
+  /// of the letters A-U, as they do on the Spectrum.
   pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
     let mut f = File::open(path)?;
     let mut bytes = vec![0; 768];
     f.read_exact(&mut bytes)?;
 
+    for i in 0..BLOCK_GRAPHICS.len() {
+      bytes.extend(block_graphic(i as u8));
+    }
+    let letters = glyph_index(b'A') * 8..glyph_index(b'A' + UDG_COUNT as u8) * 8;
+    bytes.extend_from_within(letters);
+
     Ok(Self { bytes })
   }
 
-  /// Converts the result of to_rgba into a Bevy Image.
-  fn to_image(&self, color: &Attributes, text: &str) -> Image {
-    let data = self.to_rgba(color, text);
-    Image::new(
-      Extent3d {
-        width: text.len() as u32 * 8,
-        height: 8,
-        depth_or_array_layers: 1,
-      },
-      TextureDimension::D2,
-      data,
-      TextureFormat::Rgba8Unorm,
-    )
+  /// Given some text, return a bitmap containing that text, one character per
+  /// 8x8 cell. Any characters in `text` that the Spectrum doesn't have will be
+  /// rendered as spaces.
+  pub fn to_bitmap(&self, text: &str) -> Bitmap {
+    let codes: Vec<_> = text.chars().map(charcode).collect();
+    self.codes_to_bitmap(&codes)
   }
 
-  /// Given some text, return rgba data containing that text with the given
-  /// paper and ink color. Any characters in `text` that are not in the ascii
-  /// range 32-127 will be rendered as spaces.
-  fn to_rgba(&self, color: &Attributes, text: &str) -> Vec<u8> {
-    let mut buffer = Vec::with_capacity(8 * 4 * text.len());
-
-    let ink_rgba = &color.ink_rgba()[0..4];
-    let paper_rgba = &color.paper_rgba()[0..4];
-
-    let text_bytes = text.as_bytes();
+  fn codes_to_bitmap(&self, codes: &[u8]) -> Bitmap {
+    let mut data = Vec::with_capacity(8 * codes.len());
 
     for r in 0..=7 {
-      (0..text.len()).for_each(|c| {
-        let charcode = if text_bytes[c] < 32 || text_bytes[c] > 127 {
-          32
-        } else {
-          text_bytes[c]
-        };
-
-        let char_index = charcode - 32;
-        let offset: usize = char_index as usize * 8;
-        let bitmap = &self.bytes[offset..offset + 8];
-        crate::bitmap::to_rgba(&mut buffer, &bitmap[r], ink_rgba, paper_rgba);
-      });
+      for code in codes {
+        data.push(self.glyph(*code)[r]);
+      }
     }
 
-    buffer
+    Bitmap::create(codes.len() * 8, 8, &data)
+  }
+
+  /// Returns the 8 bytes of bitmap data for the given character code.
+  /// Characters that are not in the character set are treated as spaces.
+  pub fn glyph(&self, charcode: u8) -> &[u8] {
+    let offset = glyph_index(charcode) * 8;
+    &self.bytes[offset..offset + 8]
+  }
+
+  /// Changes the given user-defined graphic (0-20) to the given bitmap data.
+  pub fn define_udg(&mut self, n: usize, glyph: [u8; 8]) {
+    assert!(n < UDG_COUNT, "No such UDG: {}", n);
+    let offset = glyph_index(FIRST_UDG + n as u8) * 8;
+    self.bytes[offset..offset + 8].copy_from_slice(&glyph);
+  }
+
+  /// Returns a bitmap with every character in the set side by side, in order.
+  pub fn to_atlas_bitmap(&self) -> Bitmap {
+    let codes: Vec<_> = (0..GLYPH_COUNT as u8).map(|i| i + FIRST_CODE).collect();
+    self.codes_to_bitmap(&codes)
+  }
+}
+
+/// Returns where the given character code is in the character set. Codes that
+/// are not in the character set are treated as spaces.
+fn glyph_index(charcode: u8) -> usize {
+  let index = charcode.wrapping_sub(FIRST_CODE) as usize;
+  if index < GLYPH_COUNT { index } else { 0 }
+}
+
+/// Draws one of the block graphics, which the Spectrum's ROM generates rather
+/// than storing. The bits of `n` set the top right, top left, bottom right and
+/// bottom left quarters of the cell.
+fn block_graphic(n: u8) -> [u8; 8] {
+  let row = |right: u8, left: u8| {
+    (if n & left != 0 { 0xf0 } else { 0 }) | (if n & right != 0 { 0x0f } else { 0 })
+  };
+
+  std::array::from_fn(|r| if r < 4 { row(0b0001, 0b0010) } else { row(0b0100, 0b1000) })
+}
+
+/// Converts to the attributes the text is drawn with.
+impl From<&TextAttributes> for Attributes {
+  fn from(attributes: &TextAttributes) -> Self {
+    Attributes {
+      flash: attributes.flash,
+      ..Attributes::new(attributes.ink, attributes.paper, attributes.bright)
+    }
   }
 }
 
@@ -202,16 +645,16 @@ mod tests {
 
   #[test]
   fn to_rgba_works() -> Result<()> {
-    let charset = Charset::load("assets/textures/charset.bin")?;
+    let charset = Charset::load("assets/charset.bin")?;
     let text = "         Central Cavern         ";
-    let rgba = charset.to_rgba(
+    let rgba = charset.to_bitmap(text).render_to_rgba(
       &Attributes {
         ink: 0,
         paper: 6,
         bright: false,
         ..Default::default()
       },
-      text,
+      &Palette::default(),
     );
 
     let file = File::create("/tmp/chars.png")?;
@@ -225,4 +668,134 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn changed_text_reuses_its_sprites() {
+    let mut app = App::new();
+    app.insert_resource(GlyphAtlas { atlas: Handle::default(), texture: Handle::default() });
+    app.insert_resource(Palette::default());
+    app.insert_resource(Flash { timer: Timer::from_seconds(FLASH_SECONDS, TimerMode::Repeating), inverted: false });
+    app.add_systems(Update, render_text);
+    let attributes = TextAttributes::new(ColorName::White, ColorName::Black);
+    let text = app.world.spawn(Text::new("AB", (0, 0), &attributes)).id();
+
+    let cells = |app: &App| -> Vec<(Entity, usize)> {
+      let parent = app.world.get::<Text>(text).unwrap().sprite_entity.unwrap();
+      let Some(children) = app.world.get::<Children>(parent) else {
+        return vec![];
+      };
+      children
+        .iter()
+        .map(|paper| {
+          let glyph = app.world.get::<Children>(*paper).unwrap()[0];
+          (*paper, app.world.get::<TextureAtlasSprite>(glyph).unwrap().index)
+        })
+        .collect()
+    };
+
+    app.update();
+    let before = cells(&app);
+    assert_eq!(before.iter().map(|(_, glyph)| *glyph).collect::<Vec<_>>(), [glyph_index(b'A'), glyph_index(b'B')]);
+
+    // The same number of characters are drawn with the same sprites.
+    app.world.get_mut::<Text>(text).unwrap().value = "CD".to_owned();
+    app.update();
+    let after = cells(&app);
+    assert_eq!(after, [(before[0].0, glyph_index(b'C')), (before[1].0, glyph_index(b'D'))]);
+
+    // Sprites are only added or removed when the length changes.
+    app.world.get_mut::<Text>(text).unwrap().value = "CDE".to_owned();
+    app.update();
+    assert_eq!(cells(&app)[..2], after);
+    assert_eq!(cells(&app).len(), 3);
+    app.world.get_mut::<Text>(text).unwrap().value = "F".to_owned();
+    app.update();
+    assert_eq!(cells(&app), [(before[0].0, glyph_index(b'F'))]);
+  }
+
+  #[test]
+  fn atlas_has_every_glyph_in_order() -> Result<()> {
+    let charset = Charset::load(CHARSET_PATH)?;
+    let atlas = charset.to_atlas_bitmap();
+
+    assert_eq!(atlas.width(), GLYPH_COUNT * 8);
+    let a = glyph_index(b'A');
+    for row in 0..8 {
+      assert_eq!(atlas.data()[row * GLYPH_COUNT + a], charset.glyph(b'A')[row]);
+    }
+    assert_eq!(glyph_index(200), glyph_index(b' '));
+
+    Ok(())
+  }
+
+  #[test]
+  fn maps_unicode_to_spectrum_characters() -> Result<()> {
+    assert_eq!(charcode('£'), 0x60);
+    assert_eq!(charcode('©'), 0x7f);
+    assert_eq!(charcode('▌'), 0x8a);
+    assert_eq!(charcode(udg(2)), 0x92);
+    assert_eq!(charcode('é'), b' ');
+
+    let mut charset = Charset::load(CHARSET_PATH)?;
+    assert_eq!(charset.to_bitmap("£1©").width(), 24);
+    assert_eq!(charset.glyph(0x8a), &[0xf0; 8]);
+    assert_eq!(charset.glyph(0x90), charset.glyph(b'A'));
+
+    charset.define_udg(0, [0xff; 8]);
+    assert_eq!(charset.glyph(0x90), &[0xff; 8]);
+
+    Ok(())
+  }
+
+  #[test]
+  fn spans_override_attributes() {
+    let red = TextAttributes::new(ColorName::White, ColorName::Red);
+    let green = TextAttributes::new(ColorName::White, ColorName::Green);
+    let text = Text::new("AIR   ", (0, 17), &red).with_span(3..6, &green);
+
+    assert_eq!(text.attributes_at(2), &red);
+    assert_eq!(text.attributes_at(3), &green);
+  }
+
+  /// Returns the text shown on each row of the given text's layout.
+  fn rows(text: &Text) -> Vec<String> {
+    let chars: Vec<char> = text.value.chars().collect();
+    let mut rows: Vec<Vec<char>> = vec![];
+    for (i, (x, y)) in text.cells() {
+      rows.resize(rows.len().max(y as usize + 1), vec![]);
+      let row = &mut rows[y as usize];
+      row.resize(row.len().max(x as usize + 1), '.');
+      row[x as usize] = chars[i];
+    }
+
+    rows.into_iter().map(|row| row.into_iter().collect()).collect()
+  }
+
+  #[test]
+  fn wraps_and_aligns_within_box() {
+    let attributes = TextAttributes::default();
+    let text = Text::new("Guide Miner Willy through 20\nlethal caverns", (0, 0), &attributes);
+
+    assert_eq!(
+      rows(&text.wrapped((12, 3), Alignment::Left)),
+      ["Guide Miner", "Willy", "through 20"]
+    );
+
+    let text = Text::new("Press ENTER to start", (0, 0), &attributes);
+    assert_eq!(rows(&text.wrapped((12, 2), Alignment::Right)), [".Press ENTER", "....to start"]);
+
+    let text = Text::new("Miner Willy", (0, 0), &attributes);
+    assert_eq!(rows(&text.wrapped((15, 1), Alignment::Centre)), ["..Miner Willy"]);
+  }
+
+  #[test]
+  fn marquee_scrolls_and_wraps_around() {
+    let mut text = Text::new("ABCDE", (0, 0), &TextAttributes::default()).marquee(3);
+    assert_eq!(rows(&text), ["ABC"]);
+
+    for _ in 0..4 {
+      text.scroll();
+    }
+    assert_eq!(rows(&text), ["EAB"]);
+  }
 }
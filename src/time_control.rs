@@ -0,0 +1,161 @@
+//! Debug controls over time: the game can be slowed down or sped up, frozen and
+//! stepped through a tick at a time, and run backwards over the last few
+//! seconds. For rewinding, a snapshot of everything that moves is kept before
+//! each tick.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+  actions::{Action, Actions},
+  actors::HorizontalMotion,
+  cavern::{CavernState, CurrentCavern},
+  guardian::{GameClock, Guardian},
+  item::Item,
+  position::Position,
+  score::Score,
+  timer::{ticking, GameSpeed, GameTick, TickControl},
+  willy::Willy,
+  GameState,
+};
+
+/// How far back the game can be rewound.
+const REWIND_SECONDS: f64 = 10.;
+
+/// The slowest and fastest the game can be run at.
+const SPEED_RANGE: (f64, f64) = (1. / 8., 8.);
+
+pub struct TimeControlPlugin;
+
+impl Plugin for TimeControlPlugin {
+  fn build(&self, app: &mut App) {
+    let capacity = (REWIND_SECONDS / GameSpeed::default().tick_period().as_secs_f64()) as usize;
+    app.insert_resource(History { snapshots: VecDeque::with_capacity(capacity), capacity });
+    app.add_systems(Update, (check_keyboard, forget_history).run_if(in_state(GameState::Playing)));
+    // Snapshots are taken before each tick, so that rewinding first goes back
+    // to how things were before the last one. This also puts them before
+    // `use_step`, which runs after the tick and may stop the next one.
+    app.add_systems(
+      FixedUpdate,
+      (
+        record_snapshot.before(GameTick).run_if(ticking),
+        rewind.run_if(|control: Res<TickControl>| control.rewinding),
+      )
+        .run_if(in_state(GameState::Playing)),
+    );
+  }
+}
+
+/// Everything that changes from tick to tick.
+struct Snapshot {
+  cavern_state: CavernState,
+  clock: GameClock,
+  score: u16,
+  willy: Option<(Willy, Position, HorizontalMotion)>,
+  guardians: Vec<(Entity, Position, HorizontalMotion)>,
+  items: Vec<(Entity, bool, HorizontalMotion)>,
+}
+
+/// The snapshots taken in the current cavern, oldest first.
+#[derive(Resource)]
+struct History {
+  snapshots: VecDeque<Snapshot>,
+  capacity: usize,
+}
+
+fn check_keyboard(actions: Actions, mut speed: ResMut<GameSpeed>, mut control: ResMut<TickControl>) {
+  let (slowest, fastest) = SPEED_RANGE;
+  if actions.just_pressed(Action::DebugSlower) && speed.0 > slowest {
+    speed.0 /= 2.;
+    info!("Speed: {}x", speed.0);
+  }
+  if actions.just_pressed(Action::DebugFaster) && speed.0 < fastest {
+    speed.0 *= 2.;
+    info!("Speed: {}x", speed.0);
+  }
+  if actions.just_pressed(Action::DebugFreeze) {
+    control.frozen = !control.frozen;
+    control.steps = 0;
+    info!("{}", if control.frozen { "Frozen" } else { "Running" });
+  }
+  if actions.just_pressed(Action::DebugStep) && control.frozen {
+    control.steps += 1;
+  }
+  let rewinding = actions.pressed(Action::DebugRewind);
+  if control.rewinding != rewinding {
+    control.rewinding = rewinding;
+  }
+}
+
+/// Snapshots from another cavern can't be restored, so they're dropped when
+/// the cavern changes.
+fn forget_history(cavern: Res<CurrentCavern>, mut history: ResMut<History>) {
+  if cavern.is_changed() {
+    history.snapshots.clear();
+  }
+}
+
+#[allow(clippy::type_complexity)]
+fn record_snapshot(
+  mut history: ResMut<History>,
+  cavern_state: Res<CavernState>,
+  clock: Res<GameClock>,
+  score: Res<Score>,
+  willy: Query<(&Willy, &Position, &HorizontalMotion)>,
+  guardians: Query<(Entity, &Position, &HorizontalMotion), With<Guardian>>,
+  items: Query<(Entity, &Item, &HorizontalMotion)>,
+) {
+  if history.snapshots.len() == history.capacity {
+    history.snapshots.pop_front();
+  }
+  history.snapshots.push_back(Snapshot {
+    cavern_state: cavern_state.clone(),
+    clock: *clock,
+    score: score.score,
+    willy: willy.get_single().ok().map(|(w, p, m)| (w.clone(), p.clone(), m.clone())),
+    guardians: guardians.iter().map(|(e, p, m)| (e, p.clone(), m.clone())).collect(),
+    items: items.iter().map(|(e, i, m)| (e, i.collected, m.clone())).collect(),
+  });
+}
+
+/// Goes back a tick, by restoring the last snapshot.
+#[allow(clippy::type_complexity)]
+fn rewind(
+  mut history: ResMut<History>,
+  mut cavern_state: ResMut<CavernState>,
+  mut clock: ResMut<GameClock>,
+  mut score: ResMut<Score>,
+  mut willy: Query<(&mut Willy, &mut Position, &mut HorizontalMotion), (Without<Guardian>, Without<Item>)>,
+  mut guardians: Query<(&mut Position, &mut HorizontalMotion), (With<Guardian>, Without<Willy>)>,
+  mut items: Query<(&mut Item, &mut HorizontalMotion), (Without<Willy>, Without<Guardian>)>,
+) {
+  let Some(snapshot) = history.snapshots.pop_back() else {
+    return;
+  };
+
+  *cavern_state = snapshot.cavern_state;
+  *clock = snapshot.clock;
+  if score.score != snapshot.score {
+    score.score = snapshot.score;
+  }
+  if let (Some((w, p, m)), Ok((mut willy, mut position, mut motion))) = (snapshot.willy, willy.get_single_mut()) {
+    *willy = w;
+    *position = p;
+    *motion = m;
+  }
+  for (entity, p, m) in snapshot.guardians {
+    if let Ok((mut position, mut motion)) = guardians.get_mut(entity) {
+      *position = p;
+      *motion = m;
+    }
+  }
+  for (entity, collected, m) in snapshot.items {
+    if let Ok((mut item, mut motion)) = items.get_mut(entity) {
+      if item.collected != collected {
+        item.collected = collected;
+      }
+      *motion = m;
+    }
+  }
+}
@@ -1,26 +1,129 @@
+//! The game moves on in ticks, at a fixed rate of roughly 14 a second. Systems
+//! that move things along by a tick go in the [GameTick] set in bevy's
+//! `FixedUpdate` schedule, which runs as many times each frame as there are
+//! ticks due, so no ticks are lost when a frame is slow.
+//!
+//! The rate can be changed with the `game.speed` setting in the config file:
+//! 2 runs the game twice as fast, and 0.5 at half speed. For debugging, ticks
+//! can also be frozen and stepped through one at a time with [TickControl].
+
+use std::time::Duration;
+
 use bevy::prelude::*;
 
-static TIMER_TICK: f32 = 0.07;
-//static TIMER_TICK: f32 = 0.2;
+use crate::{config::Config, GameState};
+
+/// How many seconds a tick lasts at normal speed. The original's main loop
+/// isn't tied to the screen refresh, and takes as long as the work in it does,
+/// so its rate still needs to be measured, either on real hardware or by
+/// counting the T-states the loop takes. Until then this is the period the
+/// game has always used.
+const TICK_SECONDS: f64 = 0.07;
 
 pub struct TimerPlugin;
 
-#[derive(Resource, Deref, DerefMut)]
-pub struct GameTimer(Timer);
+/// The systems that run once per game tick. They only run while the game is
+/// being played, so everything is frozen while paused or while any other
+/// screen is shown.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameTick;
+
+/// Whether ticks are running. While frozen, the game only moves on a tick at
+/// a time when asked to step, and while rewinding it doesn't move on at all.
+#[derive(Resource, Debug, Default)]
+pub struct TickControl {
+  pub frozen: bool,
+  /// How many ticks to run while frozen.
+  pub steps: u32,
+  pub rewinding: bool,
+}
+
+impl TickControl {
+  fn is_ticking(&self) -> bool {
+    !self.rewinding && (!self.frozen || self.steps > 0)
+  }
+}
+
+/// A run condition for systems that should only run when the game moves on by
+/// a tick.
+pub fn ticking(control: Res<TickControl>) -> bool {
+  control.is_ticking()
+}
+
+/// How many times faster than the original the game runs.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct GameSpeed(pub f64);
+
+impl GameSpeed {
+  const SETTING: &'static str = "game.speed";
+
+  /// The time between ticks at this speed.
+  pub fn tick_period(&self) -> Duration {
+    Duration::from_secs_f64(TICK_SECONDS / self.0)
+  }
+}
+
+impl Default for GameSpeed {
+  fn default() -> Self {
+    GameSpeed(1.)
+  }
+}
+
+impl From<&Config> for GameSpeed {
+  fn from(config: &Config) -> Self {
+    match config.get(GameSpeed::SETTING).map(str::parse::<f64>) {
+      None => GameSpeed::default(),
+      Some(Ok(speed)) if speed > 0. => GameSpeed(speed),
+      Some(_) => {
+        eprintln!("Error: {} should be a number above 0", GameSpeed::SETTING);
+        GameSpeed::default()
+      }
+    }
+  }
+}
 
 impl Plugin for TimerPlugin {
   fn build(&self, app: &mut App) {
-    app.add_systems(Startup, create_timer);
-    app.add_systems(PreUpdate, tick_timer);
+    app.insert_resource(GameSpeed::default());
+    app.insert_resource(TickControl::default());
+    app.configure_set(FixedUpdate, GameTick.run_if(in_state(GameState::Playing)).run_if(ticking));
+    app.add_systems(PreStartup, load_speed);
+    app.add_systems(PreUpdate, update_tick_period);
+    app.add_systems(
+      FixedUpdate,
+      use_step.after(GameTick).run_if(in_state(GameState::Playing)).run_if(ticking),
+    );
   }
 }
 
-fn create_timer(mut commands: Commands) {
-  commands.insert_resource(
-    GameTimer(Timer::from_seconds(TIMER_TICK, TimerMode::Repeating))
-  );
+fn load_speed(mut commands: Commands, config: Res<Config>) {
+  commands.insert_resource(GameSpeed::from(&*config));
+}
+
+fn update_tick_period(speed: Res<GameSpeed>, mut fixed_time: ResMut<FixedTime>) {
+  if speed.is_changed() {
+    fixed_time.period = speed.tick_period();
+  }
 }
 
-fn tick_timer(time: Res<Time>, mut timer: ResMut<GameTimer>) {
-  timer.tick(time.delta());
+fn use_step(mut control: ResMut<TickControl>) {
+  if control.frozen {
+    control.steps = control.steps.saturating_sub(1);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn speed_scales_tick_period() -> anyhow::Result<()> {
+    assert_eq!(GameSpeed::default().tick_period().as_millis(), 70);
+    assert_eq!(GameSpeed(2.).tick_period().as_micros(), GameSpeed::default().tick_period().as_micros() / 2);
+
+    assert_eq!(GameSpeed::from(&Config::try_from("game.speed = 0.5")?), GameSpeed(0.5));
+    assert_eq!(GameSpeed::from(&Config::try_from("game.speed = fast")?), GameSpeed(1.));
+
+    Ok(())
+  }
 }
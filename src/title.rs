@@ -0,0 +1,161 @@
+//! The title screen, which is shown over the top of the first cavern when the
+//! game starts, after the loading screen if there is one. The game is frozen
+//! until the start key is pressed.
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+  actions::{Action, Actions, KeyBindings},
+  color::ColorName,
+  config::Config,
+  despawn_all,
+  keyboard_matrix::InputMode,
+  position::{Layer, Position},
+  render::{SCREEN_HEIGHT, SCREEN_WIDTH},
+  text::{Alignment, Text, TextAttributes},
+  GameState,
+};
+
+pub struct TitlePlugin;
+
+impl Plugin for TitlePlugin {
+  fn build(&self, app: &mut App) {
+    app.add_systems(OnEnter(GameState::Title), show_title);
+    app.add_systems(OnExit(GameState::Title), hide_title);
+    app.add_systems(
+      Update,
+      (check_keyboard, update_input_mode_lines).chain().run_if(in_state(GameState::Title)),
+    );
+  }
+}
+
+/// Everything that makes up the title screen.
+#[derive(Component)]
+struct TitleScreen;
+
+/// The scrolling message.
+#[derive(Component)]
+struct Message;
+
+/// The line showing which input mode is in use.
+#[derive(Component)]
+struct InputModeLine;
+
+/// The message that scrolls along the bottom of the screen, much like the
+/// original game's.
+fn message(bindings: &KeyBindings, mode: InputMode) -> String {
+  let keys = match mode {
+    InputMode::Standard => format!(
+      "{} / {} = Move . . {} = Jump",
+      bindings.describe(Action::Left),
+      bindings.describe(Action::Right),
+      bindings.describe(Action::Jump),
+    ),
+    InputMode::Authentic => "Q,E,T,U,O = Left . . W,R,Y,I,P = Right . . Bottom row = Jump . . \
+      A-G = Pause . . H-ENTER = Tune On/Off"
+      .to_owned(),
+  };
+
+  format!(
+    ". . . . . . . . . . . MANIC MINER . . © BUG-BYTE ltd. 1983 . . By Matthew Smith . . . \
+    {} . . . Guide Miner Willy through 20 lethal caverns . . . . . . . . ",
+    keys
+  )
+}
+
+fn input_mode_line(bindings: &KeyBindings, mode: InputMode) -> String {
+  format!("{} = {}: {}", bindings.describe(Action::ToggleInputMode), Action::ToggleInputMode.name(), mode.name())
+}
+
+/// A black sprite that covers the whole screen, for screens shown over the top
+/// of the cavern.
+pub fn backdrop() -> SpriteBundle {
+  let mut transform = Transform::from(Position::at_char_pos(Layer::Overlay, (0, 0)));
+  transform.translation.z -= 0.5;
+
+  SpriteBundle {
+    sprite: Sprite {
+      color: Color::BLACK,
+      custom_size: Some(Vec2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32)),
+      anchor: Anchor::TopLeft,
+      ..default()
+    },
+    transform,
+    ..default()
+  }
+}
+
+fn show_title(mut commands: Commands, bindings: Res<KeyBindings>, mode: Res<InputMode>) {
+  commands.spawn((TitleScreen, backdrop()));
+
+  let text = |value: &str, pos: (u8, u8), attributes: TextAttributes| {
+    Text::new_with_layer(value, pos, &attributes, Layer::Overlay)
+  };
+  let yellow = TextAttributes::new_bright(ColorName::Yellow, ColorName::Black);
+  let white = TextAttributes::new(ColorName::White, ColorName::Black);
+  let cyan = TextAttributes::new(ColorName::Cyan, ColorName::Black);
+  let flashing = TextAttributes::new_bright(ColorName::White, ColorName::Red).flashing();
+
+  let describe = |action: Action| format!("{} = {}", bindings.describe(action), action.name());
+  let start = format!("Press {} to start", bindings.describe(Action::Start).to_uppercase());
+
+  commands.spawn_batch([
+    (TitleScreen, text("MINER WILLY", (0, 4), yellow).wrapped((32, 1), Alignment::Centre)),
+    (
+      TitleScreen,
+      text(
+        "Guide Miner Willy through 20 lethal caverns, collecting every item before his air runs out.",
+        (4, 8),
+        white,
+      )
+      .wrapped((24, 4), Alignment::Centre),
+    ),
+    (TitleScreen, text(&start, (0, 14), flashing).wrapped((32, 1), Alignment::Centre)),
+    (TitleScreen, text(&describe(Action::DefineKeys), (0, 16), cyan).wrapped((32, 1), Alignment::Centre)),
+    (TitleScreen, text(&describe(Action::ToggleClash), (1, 22), cyan).wrapped((15, 1), Alignment::Left)),
+    (TitleScreen, text(&describe(Action::CyclePalette), (16, 22), cyan).wrapped((15, 1), Alignment::Right)),
+  ]);
+  commands.spawn((
+    TitleScreen,
+    InputModeLine,
+    text(&input_mode_line(&bindings, *mode), (0, 17), cyan).wrapped((32, 1), Alignment::Centre),
+  ));
+  commands.spawn((TitleScreen, Message, text(&message(&bindings, *mode), (0, 19), yellow).marquee(32)));
+}
+
+fn hide_title(mut commands: Commands, query: Query<Entity, With<TitleScreen>>) {
+  despawn_all(&mut commands, query);
+}
+
+fn check_keyboard(
+  actions: Actions,
+  mut mode: ResMut<InputMode>,
+  mut config: ResMut<Config>,
+  mut next_state: ResMut<NextState<GameState>>,
+) {
+  if actions.just_released(Action::Start) {
+    next_state.set(GameState::Playing);
+  } else if actions.just_released(Action::DefineKeys) {
+    next_state.set(GameState::DefineKeys);
+  } else if actions.just_released(Action::ToggleInputMode) {
+    mode.toggle();
+    mode.save_to(&mut config);
+  }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_input_mode_lines(
+  bindings: Res<KeyBindings>,
+  mode: Res<InputMode>,
+  mut lines: Query<&mut Text, (With<InputModeLine>, Without<Message>)>,
+  mut messages: Query<&mut Text, (With<Message>, Without<InputModeLine>)>,
+) {
+  if mode.is_changed() && !mode.is_added() {
+    for mut text in lines.iter_mut() {
+      text.value = input_mode_line(&bindings, *mode);
+    }
+    for mut text in messages.iter_mut() {
+      text.value = message(&bindings, *mode);
+    }
+  }
+}
@@ -1,21 +1,29 @@
 use bevy::{ecs::query::Has, prelude::*};
 
 use crate::{
+  actions::{Action, Actions},
   actors::{update_actor_sprite, Actor, Direction, HorizontalMotion, Sprites},
   cavern::{CavernState, CurrentCavern},
   color::{Attributes, ColorName},
   debug::{DebugStateToggled, DebugText},
-  gamedata::{cavern::{CavernTileType, ConveyorDirection}, GameDataResource},
+  gamedata::{cavern::{Cavern, CavernTileType, Conveyor, ConveyorDirection}, GameDataResource},
+  image_cache::SpectrumImages,
   item::Item,
-  position::{vec2, Layer, Position, Relative},
-  timer::GameTimer,
+  keyboard_matrix::InputMode,
+  position::{vec2, Layer, Position, Relative, CAVERN_HEIGHT},
+  timer::GameTick,
   SCALE,
 };
 
-static JUMP_DELTAS: [f32; 16] = [
-  4.0, 4.0, 3.0, 3.0, 2.0, 2.0, 1.0, 1.0, -1.0, -1.0, -2.0, -2.0, -3.0, -3.0, -4.0, -4.0,
+static JUMP_DELTAS: [i16; 16] = [
+  4, 4, 3, 3, 2, 2, 1, 1, -1, -1, -2, -2, -3, -3, -4, -4,
 ];
 
+/// The highest Willy's jump counter can go. It counts through [JUMP_DELTAS]
+/// and then up by one for every 4 pixels he falls, and he can't fall further
+/// than the height of the cavern.
+pub const MAX_JUMP_COUNTER: u8 = JUMP_DELTAS.len() as u8 + (CAVERN_HEIGHT * 8 / 4) as u8;
+
 pub struct WillyPlugin;
 
 impl Plugin for WillyPlugin {
@@ -24,35 +32,73 @@ impl Plugin for WillyPlugin {
     app.add_systems(
       Update,
       (
-        check_wall_collision,
         check_keyboard,
-        move_willy,
         update_actor_sprite::<Willy>,
-        check_collisions,
-        check_drop,
-        check_landing,
         listen_for_debug,
         draw_debug_overlay,
         move_on_cavern_change,
-        move_on_conveyor
       )
         .chain(),
     );
+    app.add_systems(
+      FixedUpdate,
+      (
+        move_willy,
+        check_head_bump,
+        check_wall_collision,
+        walk_willy,
+        check_collisions,
+        check_drop,
+        check_landing,
+        move_on_conveyor
+      )
+        .chain()
+        .in_set(GameTick)
+        .in_set(MoveWilly),
+    );
     app.add_systems(Update, update_debug_info);
   }
 }
 
-#[derive(Component)]
+/// The systems that move Willy on by a tick, for ordering other systems in
+/// [GameTick] around.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MoveWilly;
+
+#[derive(Component, Clone, Debug)]
 pub struct Willy {
   pub airborne_status: AirborneStatus,
   jump_counter: u8,
   can_move_left: bool,
   can_move_right: bool,
-  on_conveyor: bool,
-  conveyor_direction: Direction
+  /// The conveyor Willy's standing on, if he's on a moving one.
+  conveyor: Option<ConveyorDirection>
 }
 
 impl Willy {
+  pub fn new() -> Self {
+    Willy {
+      airborne_status: AirborneStatus::NotJumpingOrFalling,
+      jump_counter: 0,
+      can_move_left: true,
+      can_move_right: true,
+      conveyor: None
+    }
+  }
+
+  /// The number of game ticks Willy has spent in the air since his last jump
+  /// or fall started.
+  pub fn jump_counter(&self) -> u8 {
+    self.jump_counter
+  }
+
+  /// Puts Willy in the air (or on the ground) as if he'd spent the given
+  /// number of ticks there.
+  pub fn set_airborne_status(&mut self, status: AirborneStatus, jump_counter: u8) {
+    self.airborne_status = status;
+    self.jump_counter = jump_counter;
+  }
+
   fn can_move(&self, direction: Direction) -> bool {
     match direction {
       Direction::Left => self.can_move_left,
@@ -61,8 +107,14 @@ impl Willy {
   }
 }
 
+impl Default for Willy {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 /// Willy's airborne status.
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 
 pub enum AirborneStatus {
   NotJumpingOrFalling,
@@ -73,6 +125,22 @@ pub enum AirborneStatus {
 }
 
 impl AirborneStatus {
+  pub const ALL: [AirborneStatus; 4] = [
+    AirborneStatus::NotJumpingOrFalling,
+    AirborneStatus::Jumping,
+    AirborneStatus::FallingSafeToLand,
+    AirborneStatus::FallingUnsafeToLand,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      AirborneStatus::NotJumpingOrFalling => "standing",
+      AirborneStatus::Jumping => "jumping",
+      AirborneStatus::FallingSafeToLand => "falling",
+      AirborneStatus::FallingUnsafeToLand => "falling_fatally",
+    }
+  }
+
   pub fn is_airborne(&self) -> bool {
     !matches!(
       self,
@@ -88,45 +156,29 @@ impl AirborneStatus {
   }
 }
 
-#[derive(Debug, Default, Resource)]
-struct KeyboardState {
-  left_pressed: bool,
-  right_pressed: bool,
-  jump_pressed: bool,
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct KeyboardState {
+  pub left_pressed: bool,
+  pub right_pressed: bool,
+  pub jump_pressed: bool,
 }
 
 fn setup(
   mut commands: Commands,
   game_data: Res<GameDataResource>,
-  mut images: ResMut<Assets<Image>>,
+  cavern: Res<CurrentCavern>,
+  mut images: SpectrumImages,
 ) {
   let willy_color = Attributes::new_transparent_bg(ColorName::White, false);
 
   let images: Vec<_> = game_data
     .willy_sprites
     .iter()
-    .map(|s| images.add(s.render_with_color(&willy_color)))
+    .map(|s| images.get(s, &willy_color))
     .collect();
 
-  // TODO: use cavern data to set spawn position
-  let willy_pos = Position::at_char_pos(Layer::Characters, (2, 13));
-
-  commands.spawn(Actor::new(
-    Willy {
-      airborne_status: AirborneStatus::NotJumpingOrFalling,
-      jump_counter: 0,
-      can_move_left: true,
-      can_move_right: true,
-      on_conveyor: false,
-      conveyor_direction: Direction::Left
-    },
-    willy_pos,
-    Sprites { images },
-    HorizontalMotion {
-      walking: false,
-      current_frame: 0,
-    },
-  ));
+  let (willy, position, motion) = start_state(&game_data.caverns[cavern.number]);
+  commands.spawn(Actor::new(willy, position, Sprites { images }, motion));
 
   commands.insert_resource(KeyboardState::default());
   commands.insert_resource(DebugState {
@@ -136,144 +188,211 @@ fn setup(
 
 #[allow(clippy::type_complexity)]
 fn move_willy(
-  timer: Res<GameTimer>,
-  keys: ResMut<KeyboardState>,
+  keys: Res<KeyboardState>,
   mut query: Query<(&mut Position, &mut Willy, &mut HorizontalMotion), With<Willy>>,
 ) {
   let (mut position, mut willy, mut motion) = query.single_mut();
 
-  if timer.just_finished() {
-    if keys.jump_pressed && !&willy.airborne_status.is_airborne() {
-      willy.airborne_status = AirborneStatus::Jumping;
-      willy.jump_counter = 0;
-    }
-
-    if !&willy.airborne_status.is_airborne() {
-      // TODO: clean this up - wtf?
+  advance(&mut willy, &mut motion, &mut position, &keys);
+}
 
-      // If no key is pressed, we're not walking.
-      if !keys.left_pressed && !keys.right_pressed {
-        motion.walking = false;
-      } else {
-        // Else we're walking
-        motion.walking = true;
-        // If only left is pressed, we're walking left.
-        if keys.left_pressed && !keys.right_pressed {
-          motion.set_direction(Direction::Left);
-        } else if !keys.left_pressed {
-          motion.set_direction(Direction::Right);
-        }
-      }
+fn walk_willy(mut query: Query<(&mut Position, &Willy, &mut HorizontalMotion)>) {
+  let (mut position, willy, mut motion) = query.single_mut();
 
-      if keys.left_pressed || keys.right_pressed {
-        motion.walking = true;
-      }
+  walk(willy, &mut motion, &mut position);
+}
 
-      if motion.walking && (!keys.right_pressed && !keys.left_pressed) {
-        motion.walking = false;
-      }
+/// Moves Willy up or down by a single game tick, based on the keys that are
+/// currently pressed and his airborne status, and decides whether he's
+/// walking. He moves sideways afterwards, in [walk], once the walls beside him
+/// have been checked at his new height.
+pub fn advance(
+  willy: &mut Willy,
+  motion: &mut HorizontalMotion,
+  position: &mut Position,
+  keys: &KeyboardState,
+) {
+  if !willy.airborne_status.is_airborne() {
+    // As in the original, the keys are read before a jump starts, so Willy
+    // jumps whichever way the keys, or the conveyor he's on, set him walking.
+    choose_direction(willy, motion, keys);
 
-      if !motion.walking && keys.right_pressed {
-        motion.walking = true;
-        motion.set_direction(Direction::Right);
-      }
+    if keys.jump_pressed {
+      willy.airborne_status = AirborneStatus::Jumping;
+      willy.jump_counter = 0;
+    }
+  }
 
-      if !motion.walking && !keys.right_pressed && keys.left_pressed {
-        motion.walking = true;
-        motion.set_direction(Direction::Left);
-      }
+  // Then, check if we're airborne. In this case, we move the y-coordinate of
+  // willy, and increment the jump animation counter.
+  if willy.airborne_status.is_airborne() {
+    if willy.jump_counter <= 15 {
+      let delta = JUMP_DELTAS[willy.jump_counter as usize];
+      position.jump(delta);
     }
 
-    if willy.on_conveyor {
-      motion.walking = true;
-      motion.set_direction(willy.conveyor_direction);
+    if willy.jump_counter > 7 {
+      willy.airborne_status = AirborneStatus::FallingSafeToLand;
     }
 
-    // Stop moving if we've hit a wall.
-    if motion.walking
-      && position.will_change_cell(motion.direction())
-      && !willy.can_move(motion.direction())
-    {
+    // In free fall!
+    if willy.jump_counter > 15 {
+      position.jump(-4);
+      // Stop walking
       motion.walking = false;
     }
 
-    // First, check if we're airborne. In this case, we move the y-coordinate of
-    // willy, and increment the jump animation counter.
-    if willy.airborne_status.is_airborne() {
-      if willy.jump_counter <= 15 {
-        let delta = JUMP_DELTAS[willy.jump_counter as usize];
-        position.jump(delta);
-      }
+    if willy.jump_counter > 20 {
+      willy.airborne_status = AirborneStatus::FallingUnsafeToLand;
+    }
 
-      if willy.jump_counter > 7 {
-        willy.airborne_status = AirborneStatus::FallingSafeToLand;
-      }
+    willy.jump_counter += 1;
+  }
+}
 
-      // In free fall!
-      if willy.jump_counter > 15 {
-        position.jump(-4.0);
-        // Stop walking
-        motion.walking = false;
-      }
+/// Decides whether Willy walks, and which way, from the keys that are pressed.
+/// A conveyor under him acts as if the key for its direction were held down,
+/// and a sticky one as if both were. With both held on a conveyor he carries on
+/// doing whatever he was doing, as in the original, so he can keep walking
+/// against a conveyor but can't turn round or stop on one.
+fn choose_direction(willy: &Willy, motion: &mut HorizontalMotion, keys: &KeyboardState) {
+  use ConveyorDirection::*;
 
-      if willy.jump_counter > 20 {
-        willy.airborne_status = AirborneStatus::FallingUnsafeToLand;
-      }
+  let left = keys.left_pressed || matches!(willy.conveyor, Some(Left | Sticky));
+  let right = keys.right_pressed || matches!(willy.conveyor, Some(Right | Sticky));
 
-      willy.jump_counter += 1;
+  match (left, right) {
+    (false, false) => motion.walking = false,
+    (true, false) => {
+      motion.walking = true;
+      motion.set_direction(Direction::Left);
     }
-
-    if motion.walking {
-      motion.step(&mut position);
+    (false, true) => {
+      motion.walking = true;
+      motion.set_direction(Direction::Right);
+    }
+    // Off a conveyor, both keys keep Willy walking the way he's facing.
+    (true, true) => {
+      if willy.conveyor.is_none() {
+        motion.walking = true;
+      }
     }
   }
 }
 
-fn check_keyboard(keys: Res<Input<KeyCode>>, mut keyboard_state: ResMut<KeyboardState>) {
-  keyboard_state.left_pressed = pressed(&keys, &LEFT_KEYS);
-  keyboard_state.right_pressed = pressed(&keys, &RIGHT_KEYS);
-  keyboard_state.jump_pressed = pressed(&keys, &[KeyCode::Space]);
-}
+/// Moves Willy sideways by a single game tick if he's walking, unless there's a
+/// wall in the way. On the ground that stops him, but in the air it only
+/// holds him back while the wall is beside him: he carries on rising or
+/// falling, and moves on again once he's clear of it, as in the original.
+pub fn walk(willy: &Willy, motion: &mut HorizontalMotion, position: &mut Position) {
+  if !motion.walking {
+    return;
+  }
 
-fn pressed(keys: &Res<'_, Input<KeyCode>>, expected: &[KeyCode]) -> bool {
-  for code in expected {
-    if keys.pressed(*code) {
-      return true;
+  if position.will_change_cell(motion.direction()) && !willy.can_move(motion.direction()) {
+    if !willy.airborne_status.is_airborne() {
+      motion.walking = false;
     }
+    return;
   }
-  false
+
+  motion.step(position);
 }
 
-const LEFT_KEYS: [KeyCode; 2] = [KeyCode::Left, KeyCode::O];
-const RIGHT_KEYS: [KeyCode; 2] = [KeyCode::Right, KeyCode::P];
+fn check_keyboard(
+  actions: Actions,
+  mode: Res<InputMode>,
+  mut keyboard_state: ResMut<KeyboardState>,
+  query: Query<&HorizontalMotion, With<Willy>>,
+) {
+  keyboard_state.left_pressed = actions.pressed(Action::Left);
+  keyboard_state.right_pressed = actions.pressed(Action::Right);
+  keyboard_state.jump_pressed = actions.pressed(Action::Jump);
+
+  // In the original game, pressing left and right together leaves Willy doing
+  // whatever he was already doing: walking on if he was walking, and standing
+  // still if he wasn't.
+  if *mode == InputMode::Authentic && keyboard_state.left_pressed && keyboard_state.right_pressed {
+    let walking = query.get_single().ok().filter(|motion| motion.walking);
+    keyboard_state.left_pressed = walking.is_some_and(|motion| motion.direction() == Direction::Left);
+    keyboard_state.right_pressed = walking.is_some_and(|motion| motion.direction() == Direction::Right);
+  }
+}
 
-// Check to see if moving left or right would collide with a wall, and should
-// therefore be disallowed. This will update the can_move_left and can_move_right
-// fields of WillyMotion.
+// Check to see if moving left or right would collide with a wall at Willy's
+// new height, and should therefore be disallowed. This will update the
+// can_move_left and can_move_right fields of WillyMotion.
 #[allow(clippy::type_complexity)]
 fn check_wall_collision(
   cavern_state: Res<CavernState>,
   mut query: Query<(&Position, &mut Willy), (With<Willy>, Or<(Changed<Willy>, Changed<Position>)>)>,
 ) {
   if query.get_single().is_ok() {
-    let (position, mut motion) = query.get_single_mut().unwrap();
+    let (position, mut willy) = query.get_single_mut().unwrap();
+    update_wall_flags(&mut willy, position, &cavern_state);
+  }
+}
+
+/// Updates whether Willy is blocked by a wall on either side of his current position.
+pub fn update_wall_flags(willy: &mut Willy, position: &Position, cavern_state: &CavernState) {
+  willy.can_move_left = !cavern_state.is_type(position, Relative::Left, CavernTileType::Wall);
+  willy.can_move_right = !cavern_state.is_type(position, Relative::Right, CavernTileType::Wall);
+}
 
-    motion.can_move_left = !cavern_state.is_type(position, Relative::Left, CavernTileType::Wall);
-    motion.can_move_right = !cavern_state.is_type(position, Relative::Right, CavernTileType::Wall);
+// Check if Willy has jumped into a ceiling
+fn check_head_bump(
+  cavern_state: Res<CavernState>,
+  mut query: Query<(&mut Willy, &mut HorizontalMotion, &mut Position), Has<Willy>>,
+) {
+  let (mut willy, mut motion, mut position) = query.get_single_mut().unwrap();
+  bump_head_if_blocked(&mut willy, &mut motion, &mut position, &cavern_state);
+}
+
+/// Stops Willy's jump if he's moved up into a wall. As in the original, he's
+/// put back at the top of the cell below the wall, stops moving sideways and
+/// falls straight down, 4 pixels a tick. Returns true if he hit his head.
+pub fn bump_head_if_blocked(
+  willy: &mut Willy,
+  motion: &mut HorizontalMotion,
+  position: &mut Position,
+  cavern_state: &CavernState,
+) -> bool {
+  if willy.airborne_status != AirborneStatus::Jumping {
+    return false;
   }
+
+  // The cells above the next cell boundary down are the ones his head is in.
+  let (x, y) = position.zx_pixel_pos();
+  let mut below_head = position.clone();
+  below_head.set_zx_pixel_pos((x, y + 8 - y.rem_euclid(8)));
+  if !cavern_state.is_type(&below_head, Relative::Above, CavernTileType::Wall) {
+    return false;
+  }
+
+  *position = below_head;
+  willy.airborne_status = AirborneStatus::FallingSafeToLand;
+  willy.jump_counter = 16;
+  motion.walking = false;
+  true
 }
 
 // Check if Willy should drop
 fn check_drop(
   cavern_state: Res<CavernState>,
-  timer: Res<GameTimer>,
   mut query: Query<(&mut Willy, &mut HorizontalMotion, &Position), Has<Willy>>,
 ) {
   let (mut willy, mut motion, position) = query.get_single_mut().unwrap();
-  if timer.just_finished()
-    && !willy.airborne_status.is_airborne()
-    && !can_stand(position, &cavern_state)
-  {
+  drop_if_unsupported(&mut willy, &mut motion, position, &cavern_state);
+}
+
+/// Starts Willy falling if he's standing on nothing.
+pub fn drop_if_unsupported(
+  willy: &mut Willy,
+  motion: &mut HorizontalMotion,
+  position: &Position,
+  cavern_state: &CavernState,
+) {
+  if !willy.airborne_status.is_airborne() && !can_stand(position, cavern_state) {
     willy.airborne_status = AirborneStatus::FallingSafeToLand;
     willy.jump_counter = 8;
     motion.walking = false;
@@ -283,39 +402,37 @@ fn check_drop(
 // Check if willy has landed on something. Ideally a floor ;)
 fn check_landing(
   cavern_state: Res<CavernState>,
-  timer: Res<GameTimer>,
   mut query: Query<(&mut Willy, &Position), Has<Willy>>,
 ) {
-  let (mut motion, position) = query.get_single_mut().unwrap();
+  let (mut willy, position) = query.get_single_mut().unwrap();
 
-  // TODO: there's a bug where we don't get some positions to check for a landing. Debug why?
-  if timer.just_finished()
-    && motion.airborne_status.is_falling()
-    && position.is_vertically_cell_aligned()
-  {
-    println!(
-      "Can land at {:?}? {}",
-      position.char_pos(),
-      can_stand(position, &cavern_state)
-    );
+  if land_if_supported(&mut willy, position, &cavern_state) {
+    println!("Landed");
   }
+}
 
-  if timer.just_finished()
-    && motion.airborne_status.is_falling()
+/// Lands Willy if he's falling and has reached a floor. Returns true if he
+/// landed.
+pub fn land_if_supported(willy: &mut Willy, position: &Position, cavern_state: &CavernState) -> bool {
+  // As in the original, he can only land when his feet are on a cell boundary.
+  // Jumps and falls both start on one and move him by a multiple of 4 pixels
+  // every other tick, so he never misses a floor.
+  if willy.airborne_status.is_falling()
     && position.is_vertically_cell_aligned()
-    && can_stand(position, &cavern_state)
+    && can_stand(position, cavern_state)
   {
-    println!("Landed");
-    motion.airborne_status = AirborneStatus::NotJumpingOrFalling;
+    willy.airborne_status = AirborneStatus::NotJumpingOrFalling;
+    return true;
   }
+
+  false
 }
 
 fn can_stand(position: &Position, cavern_state: &CavernState) -> bool {
   position
     .relative(Relative::Below)
     .iter()
-    .map(|p| cavern_state.get_tile_type(*p).can_stand())
-    .any(|v| v)
+    .any(|p| cavern_state.get_tile_type(*p).can_stand())
 }
 
 #[derive(Resource)]
@@ -410,7 +527,7 @@ fn check_collisions(
 
       // Did we intersect an item?
       for (mut item, pos) in item_query.iter_mut() {
-        if pos.char_pos() == (x, y) && !item.collected {
+        if pos.char_pos() == Some((x, y)) && !item.collected {
           item.collected = true;
           println!("Collided with ITEM at {:?}", (x, y))
         }
@@ -422,49 +539,270 @@ fn check_collisions(
 fn move_on_cavern_change(cavern: Res<CurrentCavern>, game_data: Res<GameDataResource>,
       mut query: Query<(&mut Position, &mut Willy, &mut HorizontalMotion)>) {
   if cavern.is_changed() {
-    let start = &game_data.caverns[cavern.number].willy_start;
     for (mut pos, mut willy, mut motion) in query.iter_mut() {
-      *pos = Position::at_char_pos(Layer::Characters, start.position);
-      willy.airborne_status = AirborneStatus::NotJumpingOrFalling;
-      motion.walking = false;
-      motion.set_direction(start.direction);
-      motion.current_frame = start.first_animation_frame as usize;
+      (*willy, *pos, *motion) = start_state(&game_data.caverns[cavern.number]);
     }
   }
 }
 
+/// Willy as he starts the given cavern, from its start record.
+pub fn start_state(cavern: &Cavern) -> (Willy, Position, HorizontalMotion) {
+  let start = &cavern.willy_start;
+
+  let mut willy = Willy::new();
+  // The original counts up from 2 while falling, and falls too far to survive
+  // from 12.
+  match start.airborne {
+    0 => {}
+    1 => willy.set_airborne_status(AirborneStatus::Jumping, start.jump_counter),
+    2..=11 => willy.set_airborne_status(AirborneStatus::FallingSafeToLand, 16),
+    _ => willy.set_airborne_status(AirborneStatus::FallingUnsafeToLand, 21),
+  }
+
+  let motion = HorizontalMotion {
+    walking: start.walking,
+    current_frame: start.first_animation_frame as usize,
+  };
+
+  // As with guardians, the animation frame says how far across his cell he is.
+  let mut position = Position::at_char_pos(Layer::Characters, start.position);
+  let (x, _) = position.zx_pixel_pos();
+  position.set_zx_pixel_pos((x + 2 * (motion.current_frame % 4) as i16, i16::from(start.pixel_y)));
+
+  update_conveyor_flags(&mut willy, &position, &cavern.conveyor);
+
+  (willy, position, motion)
+}
+
 /// Move willy when he's standing on a conveyor
 fn move_on_conveyor(cavern: Res<CurrentCavern>, game_data: Res<GameDataResource>,
     mut query: Query<(&Position, &mut Willy)>) {
 
   let conveyor = &game_data.caverns[cavern.number].conveyor;
 
+  for (pos, mut willy) in query.iter_mut() {
+    update_conveyor_flags(&mut willy, pos, conveyor);
+  }
+}
+
+/// Updates whether Willy is standing on the given conveyor, and which way it's
+/// going.
+pub fn update_conveyor_flags(willy: &mut Willy, pos: &Position, conveyor: &Conveyor) {
   let (conx, cony) = conveyor.position;
 
-  for (pos, mut willy) in query.iter_mut() {
-    willy.on_conveyor = false;
-    if !willy.airborne_status.is_airborne() && conveyor.direction.is_moving() {
-      for (cx, cy) in pos.relative(Relative::Below) {
-        if cy == cony && cx >= conx && cx < conx + conveyor.length {
-          // Willy is standing on a conveyor. Make him move in the relevant direction.
-          match conveyor.direction {
-            ConveyorDirection::Left => {
-              willy.on_conveyor = true;
-              willy.conveyor_direction = Direction::Left;
-            },
-            ConveyorDirection::Right => {
-              willy.on_conveyor = true;
-              willy.conveyor_direction = Direction::Right;
-            },
-            ConveyorDirection::Sticky => {
-              willy.on_conveyor = true;
-              willy.conveyor_direction = Direction::Right;
-            }
-            _ => {}
-          }
-        }
-      }
+  willy.conveyor = None;
+  if !willy.airborne_status.is_airborne() && conveyor.direction.is_moving() {
+    let on_conveyor = pos
+      .relative(Relative::Below)
+      .into_iter()
+      .any(|(cx, cy)| cy == cony && cx >= conx && cx < conx + conveyor.length);
+    if on_conveyor {
+      willy.conveyor = Some(conveyor.direction);
     }
   }
+}
+
+/// Runs a single game tick of Willy's movement outside of bevy, applying the
+/// same steps in the same order as the systems in [WillyPlugin]. This is used
+/// to simulate Willy when searching for routes through a cavern.
+pub fn tick(
+  willy: &mut Willy,
+  motion: &mut HorizontalMotion,
+  position: &mut Position,
+  keys: &KeyboardState,
+  cavern_state: &CavernState,
+  conveyor: &Conveyor,
+) {
+  advance(willy, motion, position, keys);
+  bump_head_if_blocked(willy, motion, position, cavern_state);
+  update_wall_flags(willy, position, cavern_state);
+  walk(willy, motion, position);
+  drop_if_unsupported(willy, motion, position, cavern_state);
+  land_if_supported(willy, position, cavern_state);
+  update_conveyor_flags(willy, position, conveyor);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NO_CONVEYOR: Conveyor = Conveyor { direction: ConveyorDirection::Off, position: (0, 0), length: 0 };
 
+  /// A floor along row 10, with Willy standing on it at column 10.
+  fn floor(extra: &[((u8, u8), CavernTileType)]) -> (CavernState, Willy, HorizontalMotion, Position) {
+    let mut tiles: Vec<_> = (0..32).map(|x| ((x, 10), CavernTileType::Floor)).collect();
+    tiles.extend_from_slice(extra);
+    let position = Position::at_char_pos(Layer::Characters, (10, 8));
+    (CavernState::with_tiles(&tiles), Willy::new(), HorizontalMotion::frozen(), position)
+  }
+
+  fn jump() -> KeyboardState {
+    KeyboardState { jump_pressed: true, ..Default::default() }
+  }
+
+  #[test]
+  fn jumping_under_a_low_ceiling_bumps_head() {
+    let (cavern_state, mut willy, mut motion, mut position) = floor(&[((11, 7), CavernTileType::Wall)]);
+
+    // He's knocked straight back down to where he started, and lands.
+    tick(&mut willy, &mut motion, &mut position, &jump(), &cavern_state, &NO_CONVEYOR);
+    assert_eq!(position.zx_pixel_pos(), (80, 64));
+    assert!(!willy.airborne_status.is_airborne());
+  }
+
+  #[test]
+  fn jumping_under_a_higher_ceiling_stops_below_it() {
+    let (cavern_state, mut willy, mut motion, mut position) = floor(&[((10, 6), CavernTileType::Wall)]);
+
+    let mut highest = 64;
+    for _ in 0..3 {
+      tick(&mut willy, &mut motion, &mut position, &jump(), &cavern_state, &NO_CONVEYOR);
+      highest = highest.min(position.zx_pixel_pos().1);
+    }
+    // He rises into the empty row above him, but no further.
+    assert_eq!(highest, 56);
+    assert!(willy.airborne_status.is_falling());
+    for _ in 0..8 {
+      tick(&mut willy, &mut motion, &mut position, &KeyboardState::default(), &cavern_state, &NO_CONVEYOR);
+    }
+    assert_eq!(position.zx_pixel_pos(), (80, 64));
+
+    let (cavern_state, mut willy, mut motion, mut position) = floor(&[]);
+    for _ in 0..8 {
+      tick(&mut willy, &mut motion, &mut position, &jump(), &cavern_state, &NO_CONVEYOR);
+    }
+    assert_eq!(position.zx_pixel_pos(), (80, 44));
+  }
+
+  #[test]
+  fn willy_lands_on_a_ledge_on_the_way_down_from_a_jump() {
+    let ledge: Vec<_> = (12..20).map(|x| ((x, 9), CavernTileType::Floor)).collect();
+    let (cavern_state, mut willy, mut motion, mut position) = floor(&ledge);
+    let keys = KeyboardState { right_pressed: true, jump_pressed: true, ..Default::default() };
+    motion.walking = true;
+
+    let mut ys = vec![];
+    while willy.airborne_status.is_airborne() || ys.is_empty() {
+      tick(&mut willy, &mut motion, &mut position, &keys, &cavern_state, &NO_CONVEYOR);
+      ys.push(position.zx_pixel_pos().1);
+    }
+
+    // He comes down past the ledge at heights that aren't on a cell boundary,
+    // and lands as soon as his feet reach it.
+    assert_eq!(ys[7..], [44, 45, 46, 48, 50, 53, 56]);
+    assert_eq!(position.zx_pixel_pos(), (108, 56));
+  }
+
+  #[test]
+  fn walls_in_mid_air_only_hold_willy_back_while_beside_him() {
+    // A wall in the row above his head, two cells to the right of where he
+    // starts.
+    let (cavern_state, mut willy, mut motion, mut position) = floor(&[((13, 7), CavernTileType::Wall)]);
+    let keys = KeyboardState { right_pressed: true, jump_pressed: true, ..Default::default() };
+    motion.walking = true;
+
+    let mut xs = vec![];
+    for _ in 0..16 {
+      tick(&mut willy, &mut motion, &mut position, &keys, &cavern_state, &NO_CONVEYOR);
+      xs.push(position.zx_pixel_pos().0);
+    }
+
+    // He's held at the end of cell 11 while the wall is beside him, even when
+    // it's only beside his feet, but still rises and falls, and carries on to
+    // the right once he's back down.
+    assert_eq!(xs[..6], [82, 84, 86, 88, 90, 92]);
+    assert!(xs[6..15].iter().all(|x| *x == 94));
+    assert_eq!(xs[15], 96);
+    assert!(motion.walking);
+    assert_eq!(position.zx_pixel_pos().1, 64);
+  }
+
+  /// A conveyor along row 10, under Willy, going the given way.
+  fn conveyor(direction: ConveyorDirection) -> Conveyor {
+    Conveyor { direction, position: (0, 10), length: 32 }
+  }
+
+  /// Where Willy is after each of the given ticks on a conveyor, starting out
+  /// walking or standing facing right.
+  fn ride(direction: ConveyorDirection, walking: bool, keys: &[KeyboardState]) -> Vec<(i16, i16)> {
+    let (cavern_state, mut willy, mut motion, mut position) = floor(&[]);
+    let conveyor = conveyor(direction);
+    motion.walking = walking;
+    update_conveyor_flags(&mut willy, &position, &conveyor);
+
+    keys
+      .iter()
+      .map(|keys| {
+        tick(&mut willy, &mut motion, &mut position, keys, &cavern_state, &conveyor);
+        position.zx_pixel_pos()
+      })
+      .collect()
+  }
+
+  const IDLE: KeyboardState = KeyboardState { left_pressed: false, right_pressed: false, jump_pressed: false };
+  const RIGHT: KeyboardState = KeyboardState { left_pressed: false, right_pressed: true, jump_pressed: false };
+
+  #[test]
+  fn willy_starts_where_each_cavern_says() -> anyhow::Result<()> {
+    let game_data = crate::gamedata::GameData::load(crate::gamedata::GAME_DATA_PATH)?;
+
+    let (willy, position, motion) = start_state(&game_data.caverns[0]);
+    assert_eq!(position.zx_pixel_pos(), (16, 104));
+    assert_eq!(motion.direction(), Direction::Right);
+    assert!(!motion.walking && !willy.airborne_status.is_airborne());
+
+    // In the Processing Plant he starts facing left on his last frame, so at
+    // the right of his cell.
+    let (_, position, motion) = start_state(&game_data.caverns[5]);
+    assert_eq!(position.zx_pixel_pos(), (126, 24));
+    assert_eq!(motion.current_frame, 7);
+
+    Ok(())
+  }
+
+  #[test]
+  fn conveyors_carry_willy_along() {
+    let xs: Vec<_> = ride(ConveyorDirection::Left, false, &[IDLE; 3]).iter().map(|(x, _)| *x).collect();
+    assert_eq!(xs, [78, 76, 74]);
+    let xs: Vec<_> = ride(ConveyorDirection::Right, false, &[IDLE; 3]).iter().map(|(x, _)| *x).collect();
+    assert_eq!(xs, [82, 84, 86]);
+    // Conveyors that are off leave him where he is.
+    assert_eq!(ride(ConveyorDirection::Off, false, &[IDLE; 3]), [(80, 64); 3]);
+  }
+
+  #[test]
+  fn willy_can_walk_against_a_conveyor_but_not_turn_round_on_one() {
+    // Walking right against a left conveyor, he keeps going while right is
+    // held, and is carried back once it's let go.
+    let xs: Vec<_> = ride(ConveyorDirection::Left, true, &[RIGHT, RIGHT, IDLE, IDLE]).iter().map(|(x, _)| *x).collect();
+    assert_eq!(xs, [82, 84, 82, 80]);
+
+    // Once he's being carried left, pressing right doesn't turn him round.
+    let xs: Vec<_> = ride(ConveyorDirection::Left, false, &[IDLE, RIGHT, RIGHT]).iter().map(|(x, _)| *x).collect();
+    assert_eq!(xs, [78, 76, 74]);
+  }
+
+  #[test]
+  fn jumping_off_a_conveyor_goes_its_way() {
+    let jump = KeyboardState { jump_pressed: true, ..Default::default() };
+    let positions = ride(ConveyorDirection::Left, false, &[jump; 16]);
+
+    // He jumps to the left, and lands on the conveyor again.
+    let xs: Vec<_> = positions.iter().map(|(x, _)| *x).collect();
+    assert_eq!(xs, (0..16).map(|tick| 78 - 2 * tick).collect::<Vec<_>>());
+    assert_eq!(positions[7], (64, 44));
+    assert_eq!(positions[15], (48, 64));
+  }
+
+  #[test]
+  fn sticky_conveyors_keep_willy_doing_whatever_he_was_doing() {
+    let left = KeyboardState { left_pressed: true, ..Default::default() };
+
+    // Standing still, he can't walk off either way.
+    assert_eq!(ride(ConveyorDirection::Sticky, false, &[IDLE, left, RIGHT]), [(80, 64); 3]);
+
+    // Walking, he can't stop or turn round.
+    let xs: Vec<_> = ride(ConveyorDirection::Sticky, true, &[IDLE, left, RIGHT]).iter().map(|(x, _)| *x).collect();
+    assert_eq!(xs, [82, 84, 86]);
+  }
 }